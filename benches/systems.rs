@@ -0,0 +1,93 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use ecs_pong::components;
+use ecs_pong::scene;
+use ecs_pong::systems;
+use legion::prelude::*;
+
+/// Balls of the stress scene every system is measured on
+const BALLS: usize = 2000;
+
+/// Cell size of the uniform grid, which fits a ball with its margin
+const CELL_SIZE: f32 = 8.0;
+
+/// Stress scene whose hitboxes were added to the collision world, using a
+/// uniform grid of `cell_size` as broad phase if given
+fn stress(cell_size: Option<f32>) -> (World, Resources) {
+    let mut world = Universe::new().create_world();
+    let mut resources = Resources::default();
+    scene::insert_resources(&mut resources, 0);
+    resources.insert(Vec::<components::RenderInfo>::new());
+    if let Some(cell_size) = cell_size {
+        scene::use_uniform_grid(&mut resources, cell_size);
+    }
+    scene::insert_stress(&mut world, &mut resources, BALLS);
+    Schedule::builder()
+        .add_system(systems::build_map_entity_collision_handle_system())
+        .add_system(systems::build_collision_system())
+        .flush()
+        .build()
+        .execute(&mut world, &mut resources);
+    (world, resources)
+}
+
+fn bench_movement(c: &mut Criterion) {
+    let (mut world, mut resources) = stress(None);
+    let mut schedule = Schedule::builder()
+        .add_system(systems::build_movement_system())
+        .build();
+    c.bench_function("do_movement", |b| {
+        b.iter(|| schedule.execute(&mut world, &mut resources))
+    });
+}
+
+/// Only the collision system is timed, the balls are moved and bounced
+/// between two measurements so every tick starts and stops collisions
+fn bench_collision(c: &mut Criterion) {
+    let mut group = c.benchmark_group("do_collision");
+    for (name, cell_size) in [("dbvt", None), ("uniform_grid", Some(CELL_SIZE))] {
+        let (mut world, mut resources) = stress(cell_size);
+        let mut movement = Schedule::builder()
+            .add_system(systems::build_events_system::<components::CollisionEvent>())
+            .add_system(systems::build_movement_system())
+            .build();
+        let mut collision = Schedule::builder()
+            .add_system(systems::build_collision_system())
+            .build();
+        let mut response = Schedule::builder()
+            .add_system(systems::build_handle_ball_wall_collision())
+            .add_system(systems::build_handle_ball_ball_collision())
+            .build();
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = std::time::Duration::default();
+                for _ in 0..iters {
+                    movement.execute(&mut world, &mut resources);
+                    let started = std::time::Instant::now();
+                    collision.execute(&mut world, &mut resources);
+                    elapsed += started.elapsed();
+                    response.execute(&mut world, &mut resources);
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_dispatch_render(c: &mut Criterion) {
+    let (mut world, mut resources) = stress(None);
+    let mut schedule = Schedule::builder()
+        .add_system(systems::build_dispatch_render_system())
+        .build();
+    c.bench_function("dispatch_render", |b| {
+        b.iter(|| schedule.execute(&mut world, &mut resources))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_movement,
+    bench_collision,
+    bench_dispatch_render
+);
+criterion_main!(benches);
@@ -0,0 +1,119 @@
+use ecs_pong::components;
+use ecs_pong::net;
+use ecs_pong::options::Options;
+use ecs_pong::replay::{Playback, Replay, Settings};
+use ecs_pong::scene;
+use ecs_pong::snapshot;
+use ecs_pong::spectate;
+use legion::prelude::*;
+
+fn main() {
+    let mut options = Options::from_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!(
+            "usage: headless [--seed <n>] [--ticks <n>] [--record <file>] [--replay <file>]
+                [--host <addr>] [--broadcast <addr>] [--players <n>] [--multi-ball]
+                [--power-ups] [--obstacles] [--breakout] [--layout <file>] [--stress <balls>]
+                [--grid <cell size>]"
+        );
+        std::process::exit(2);
+    });
+    let mut playback = options
+        .replay
+        .as_ref()
+        .map(|path| Playback::new(Replay::load(path).expect("Failed to load replay")));
+    // a replay is simulated with the settings it was recorded with
+    if let Some(playback) = &playback {
+        playback.replay().settings.apply(&mut options);
+    }
+    let seed = match &playback {
+        Some(playback) => playback.replay().seed,
+        None => options.seed.unwrap_or(0),
+    };
+
+    let universe = Universe::new();
+    let mut world = universe.create_world();
+    let mut resources = Resources::default();
+    let players =
+        scene::setup(&options, seed, &mut world, &mut resources).expect("Failed to set up scene");
+    let mut schedule = scene::build_simulation().flush().build();
+
+    let scene_hash = snapshot::world_hash(&world);
+    if let Some(playback) = &playback {
+        if playback.replay().scene_hash != scene_hash {
+            eprintln!("Replay was recorded in a different scene");
+            std::process::exit(1);
+        }
+    }
+    let mut recording = options
+        .record
+        .as_ref()
+        .map(|_| Replay::new(seed, Settings::of(&options), scene_hash));
+
+    let mut host = options.host.map(|addr| {
+        net::control_remotely(&mut world, 1, 0.1);
+        net::Host::bind(addr, 1, scene_hash, 50).expect("Failed to host match")
+    });
+    let mut broadcaster = options
+        .broadcast
+        .map(|addr| spectate::Broadcaster::bind(addr, 50).expect("Failed to start broadcast"));
+    let realtime = host.is_some() || broadcaster.is_some();
+
+    let started = std::time::Instant::now();
+    let mut tick = 0;
+    while options.ticks.is_none_or(|ticks| tick < ticks) {
+        if let Some(playback) = &mut playback {
+            if !playback.apply(&mut resources) {
+                break;
+            }
+        }
+        if let Some(host) = &mut host {
+            host.receive(&mut resources).expect("Failed to receive");
+        }
+        if let Some(recording) = &mut recording {
+            recording.record(&resources);
+        }
+        schedule.execute(&mut world, &mut resources);
+        if let Some(host) = &mut host {
+            host.send_state(&world, &resources)
+                .expect("Failed to send state");
+        }
+        if let Some(broadcaster) = &mut broadcaster {
+            broadcaster
+                .broadcast(&world, &resources)
+                .expect("Failed to broadcast");
+        }
+        // matches watched by others run in real time
+        if realtime {
+            std::thread::sleep(std::time::Duration::from_micros(200));
+        }
+        tick += 1;
+        if *resources.get::<components::GameState>().unwrap() == components::GameState::GameOver {
+            break;
+        }
+    }
+
+    let score = resources.get::<components::Match>().unwrap().score;
+    let score: Vec<String> = score[..players]
+        .iter()
+        .map(|one_score| one_score.to_string())
+        .collect();
+    if options.stress.is_some() {
+        println!("Simulated {} ticks in {:.2?}", tick, started.elapsed());
+    } else {
+        println!("Simulated {} ticks, score {}", tick, score.join(":"));
+    }
+    println!("World hash {:016x}", snapshot::world_hash(&world));
+    if let (Some(recording), Some(path)) = (&mut recording, &options.record) {
+        recording.final_hash = snapshot::world_hash(&world);
+        recording.save(path).expect("Failed to save replay");
+    }
+    if let Some(playback) = &playback {
+        if playback.verify(&world) {
+            println!("Replay reproduced the recorded match");
+        } else {
+            println!("Replay diverged from the recorded match");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use ecs_pong::tournament::{self, Config, Entrant, Format};
+
+const USAGE: &str = "usage: tournament <entrants.json> [--elimination] [--games <n>] [--threads <n>] [--seed <n>] [--ticks <n>] [--points <n>] [--json <file>] [--standings-csv <file>] [--matches-csv <file>]";
+
+/// Files the report of the tournament is written to
+#[derive(Default)]
+struct Outputs {
+    json: Option<std::path::PathBuf>,
+    standings_csv: Option<std::path::PathBuf>,
+    matches_csv: Option<std::path::PathBuf>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<(std::path::PathBuf, Config, Outputs), String> {
+    let mut entrants = None;
+    let mut config = Config::default();
+    let mut outputs = Outputs::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--elimination" => config.format = Format::Elimination,
+            "--games" => config.games = parse(&value()?)?,
+            "--threads" => config.threads = parse(&value()?)?,
+            "--seed" => config.seed = parse(&value()?)?,
+            "--ticks" => config.max_ticks = parse(&value()?)?,
+            "--points" => config.points_to_win = parse(&value()?)?,
+            "--json" => outputs.json = Some(value()?.into()),
+            "--standings-csv" => outputs.standings_csv = Some(value()?.into()),
+            "--matches-csv" => outputs.matches_csv = Some(value()?.into()),
+            _ if !arg.starts_with("--") && entrants.is_none() => entrants = Some(arg.into()),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    let entrants = entrants.ok_or_else(|| "missing entrants file".to_string())?;
+    Ok((entrants, config, outputs))
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {}", value))
+}
+
+fn main() {
+    let (path, config, outputs) = parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    });
+    let entrants = Entrant::load(&path).expect("Failed to load entrants");
+    if entrants.len() < 2 {
+        eprintln!("A tournament needs at least two entrants");
+        std::process::exit(2);
+    }
+
+    let report = tournament::run(&entrants, &config);
+    println!(
+        "{:<4} {:<16} {:>6} {:>4} {:>4} {:>4} {:>8} {:>17}",
+        "rank", "name", "played", "won", "drew", "lost", "win rate", "95% interval"
+    );
+    for (rank, standing) in report.standings.iter().enumerate() {
+        println!(
+            "{:<4} {:<16} {:>6} {:>4} {:>4} {:>4} {:>8.3} [{:.3}, {:.3}]",
+            rank + 1,
+            standing.name,
+            standing.played,
+            standing.wins,
+            standing.draws,
+            standing.losses,
+            standing.win_rate,
+            standing.confidence[0],
+            standing.confidence[1]
+        );
+    }
+    if let Some(champion) = &report.champion {
+        println!("Champion {}", champion);
+    }
+
+    if let Some(path) = &outputs.json {
+        report.save_json(path).expect("Failed to save report");
+    }
+    let create = |path: &std::path::Path| {
+        std::io::BufWriter::new(std::fs::File::create(path).expect("Failed to create csv file"))
+    };
+    if let Some(path) = &outputs.standings_csv {
+        report
+            .write_standings_csv(create(path))
+            .expect("Failed to write standings");
+    }
+    if let Some(path) = &outputs.matches_csv {
+        report
+            .write_matches_csv(create(path))
+            .expect("Failed to write matches");
+    }
+}
@@ -0,0 +1,137 @@
+use super::components;
+use super::scene;
+use legion::prelude::*;
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+/// Arrangement of the bricks of a breakout match. Every character of a row is
+/// a brick with that many hit points, `.` and spaces leave a gap.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub rows: Vec<String>,
+    pub brick_half_extents: [f32; 2],
+    /// Distance of the first row from the top of the arena
+    pub top: f32,
+    /// Space between two neighbouring bricks
+    pub gap: f32,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            rows: [
+                "3333333333",
+                "2222222222",
+                "2222222222",
+                "1111111111",
+                "1111111111",
+            ]
+            .iter()
+            .map(|row| row.to_string())
+            .collect(),
+            brick_half_extents: [26.0, 8.0],
+            top: 80.0,
+            gap: 4.0,
+        }
+    }
+}
+
+impl Layout {
+    pub fn parse(json: &str) -> serde_json::Result<Layout> {
+        serde_json::from_str(json)
+    }
+
+    pub fn load(path: &std::path::Path) -> std::io::Result<Layout> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        serde_json::from_reader(file).map_err(std::io::Error::from)
+    }
+
+    /// Locations and hit points of all bricks, centered horizontally in the arena
+    pub fn bricks(&self) -> Vec<(na::Vector2<f32>, components::HitPoints)> {
+        let half_extents = na::Vector2::from(self.brick_half_extents);
+        let step = half_extents * 2.0 + na::Vector2::new(self.gap, self.gap);
+        let columns = self.rows.iter().map(|row| row.chars().count()).max();
+        let left = 325.0 - (columns.unwrap_or(0) as f32 - 1.0) * step[0] / 2.0;
+        let mut bricks = Vec::new();
+        for (row_index, row) in self.rows.iter().enumerate() {
+            for (column, cell) in row.chars().enumerate() {
+                if let Some(remaining) = cell.to_digit(10).filter(|remaining| *remaining > 0) {
+                    let location = na::Vector2::new(
+                        left + column as f32 * step[0],
+                        4.0 + self.top + half_extents[1] + row_index as f32 * step[1],
+                    );
+                    bricks.push((location, components::HitPoints { remaining }));
+                }
+            }
+        }
+        bricks
+    }
+}
+
+/// Balls a player loses until the breakout match is over
+pub const LIVES: u32 = 3;
+
+/// Adjusts the resources of `scene::insert_resources` to a breakout match
+pub fn insert_resources(resources: &mut Resources) {
+    let mut config = resources.get_mut::<components::MatchConfig>().unwrap();
+    config.lives = LIVES;
+    config.serve_location = na::Vector2::new(325.0, 450.0);
+    let mut game = resources.get_mut::<components::Match>().unwrap();
+    game.receiver = 0;
+}
+
+/// Inserts a breakout match of player 0 with its paddle at the bottom of the
+/// arena, which is closed by walls on the other sides
+pub fn insert_components(world: &mut World, layout: &Layout) {
+    world.insert((components::Ball, ()), vec![scene::ball()]);
+    let (trans, shape, hitbox, player, motion, layer) =
+        scene::paddle(na::Vector2::new(325.0, 734.0), true, 0);
+    world.insert(
+        (components::Barrier, ()),
+        vec![(
+            trans,
+            shape,
+            hitbox,
+            player,
+            motion,
+            layer,
+            components::InputController { max_speed: 0.1 },
+            components::Effects::default(),
+        )],
+    );
+    world.insert(
+        (components::Wall, ()),
+        vec![
+            scene::wall(na::Vector2::new(20.0, 384.0), na::Vector2::new(2.0, 384.0)),
+            scene::wall(na::Vector2::new(630.0, 384.0), na::Vector2::new(2.0, 384.0)),
+            scene::wall(na::Vector2::new(325.0, 2.0), na::Vector2::new(305.0, 2.0)),
+        ],
+    );
+    world.insert(
+        (components::Goal, ()),
+        vec![scene::goal(0, na::Vector2::new(325.0, 766.0), true)],
+    );
+    let half_extents = na::Vector2::from(layout.brick_half_extents);
+    world.insert(
+        (components::Brick, ()),
+        layout.bricks().into_iter().map(|(location, hit_points)| {
+            (
+                components::Transformation {
+                    location,
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                components::RenderShape {
+                    color: hit_points.color(),
+                    half_extents,
+                },
+                components::Hitbox {
+                    shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(half_extents)),
+                    slap_handle: None,
+                },
+                hit_points,
+                components::CollisionLayer::fixed(),
+            )
+        }),
+    );
+}
@@ -0,0 +1,109 @@
+use super::components;
+use legion::prelude::*;
+
+/// Orders `pair` so that the entity tagged `T1` comes first, if the other one
+/// is tagged `T2`
+pub fn sort_collision_pair_by_tag<T1, T2>(
+    world: &impl EntityStore,
+    pair: &[Entity; 2],
+) -> Option<(Entity, Entity)>
+where
+    T1: Clone + PartialEq + Send + Sync + 'static,
+    T2: Clone + PartialEq + Send + Sync + 'static,
+{
+    let opt_first = pair
+        .iter()
+        .enumerate()
+        .find(|e| world.get_tag::<T1>(*e.1).is_some());
+    if let Some(first) = opt_first {
+        let second_idx = 1 - first.0;
+        if world.get_tag::<T2>(pair[second_idx]).is_some() {
+            return Some((*first.1, pair[second_idx]));
+        }
+    }
+    None
+}
+
+type Handler = Box<dyn FnMut(&mut World, &mut Resources, &[Entity; 2])>;
+
+/// Handlers of collisions between entities with a certain pair of tags, which
+/// are dispatched together once per tick. The built-in collision responses are
+/// systems of the simulation, this registry is only for handlers added from
+/// outside of it. Handlers deleting entities should use `remove_from_play`.
+#[derive(Default)]
+pub struct CollisionHandlers {
+    handlers: Vec<Handler>,
+}
+
+impl CollisionHandlers {
+    /// Registers `handler` for collisions of an entity tagged `T1` with one
+    /// tagged `T2`, which are passed to it in this order
+    pub fn on_collision<T1, T2, F>(&mut self, mut handler: F) -> &mut CollisionHandlers
+    where
+        T1: Clone + PartialEq + Send + Sync + 'static,
+        T2: Clone + PartialEq + Send + Sync + 'static,
+        F: FnMut(&mut World, &mut Resources, Entity, Entity) + 'static,
+    {
+        self.handlers.push(Box::new(move |world, resources, pair| {
+            if let Some((first, second)) = sort_collision_pair_by_tag::<T1, T2>(world, pair) {
+                handler(world, resources, first, second);
+            }
+        }));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Passes every pair of `collisions` to the matching handlers, one handler
+    /// after the other in the order they were registered in. Pairs with an
+    /// entity deleted by an earlier handler are skipped.
+    pub fn dispatch(
+        &mut self,
+        world: &mut World,
+        resources: &mut Resources,
+        collisions: &[[Entity; 2]],
+    ) {
+        for handler in &mut self.handlers {
+            for pair in collisions {
+                if pair.iter().all(|entity| world.is_alive(*entity)) {
+                    handler(world, resources, pair);
+                }
+            }
+        }
+    }
+
+    /// Schedule step dispatching the pairs which started touching during the tick
+    pub fn into_step(mut self) -> impl FnMut(&mut World, &mut Resources) {
+        move |world, resources| {
+            if self.is_empty() {
+                return;
+            }
+            let started: Vec<[Entity; 2]> = match resources.get::<components::CollisionEvents>() {
+                Some(events) => events.started().copied().collect(),
+                None => return,
+            };
+            self.dispatch(world, resources, &started);
+        }
+    }
+}
+
+/// Deletes `entity` and removes its collision object from the collision world
+/// and the map of collision handles in `resources`
+pub fn remove_from_play(world: &mut World, resources: &Resources, entity: Entity) {
+    let handle = world
+        .get_component::<components::Hitbox>(entity)
+        .and_then(|hitbox| hitbox.slap_handle);
+    if let Some(handle) = handle {
+        resources
+            .get_mut::<ncollide2d::pipeline::world::CollisionWorld<f32, ()>>()
+            .unwrap()
+            .remove(&[handle]);
+        resources
+            .get_mut::<std::collections::HashMap<ncollide2d::pipeline::CollisionObjectSlabHandle, Entity>>()
+            .unwrap()
+            .remove(&handle);
+    }
+    world.delete(entity);
+}
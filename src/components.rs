@@ -1,14 +1,15 @@
 use nalgebra as na;
+use serde::{Deserialize, Serialize};
 
 // Actual components
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Transformation {
     pub location: na::Vector2<f32>,
     pub scale: na::Vector2<f32>,
     pub rotation: f32,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Velocity {
     pub velocity: f32,
 }
@@ -18,20 +19,552 @@ pub struct Hitbox {
     pub slap_handle: Option<ncollide2d::pipeline::CollisionObjectSlabHandle>,
 }
 
+/// Serializable description of the shape of a `Hitbox`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum HitboxShape {
+    Cuboid { half_extents: [f32; 2] },
+    Ball { radius: f32 },
+}
+
+impl HitboxShape {
+    /// Describes `shape`, if it is one of the supported shapes
+    pub fn of(shape: &dyn ncollide2d::shape::Shape<f32>) -> Option<HitboxShape> {
+        if let Some(cuboid) = shape.as_shape::<ncollide2d::shape::Cuboid<f32>>() {
+            return Some(HitboxShape::Cuboid {
+                half_extents: (*cuboid.half_extents()).into(),
+            });
+        }
+        shape
+            .as_shape::<ncollide2d::shape::Ball<f32>>()
+            .map(|ball| HitboxShape::Ball {
+                radius: ball.radius(),
+            })
+    }
+
+    pub fn to_shape(self) -> std::sync::Arc<dyn ncollide2d::shape::Shape<f32>> {
+        match self {
+            HitboxShape::Cuboid { half_extents } => std::sync::Arc::new(
+                ncollide2d::shape::Cuboid::new(na::Vector2::from(half_extents)),
+            ),
+            HitboxShape::Ball { radius } => {
+                std::sync::Arc::new(ncollide2d::shape::Ball::new(radius))
+            }
+        }
+    }
+}
+
+/// A hitbox is stored as the description of its shape. Deserialized hitboxes
+/// are not registered in the `CollisionWorld` yet.
+impl Serialize for Hitbox {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HitboxShape::of(&*self.shape)
+            .ok_or_else(|| serde::ser::Error::custom("unsupported hitbox shape"))?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Hitbox {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Hitbox, D::Error> {
+        Ok(Hitbox {
+            shape: HitboxShape::deserialize(deserializer)?.to_shape(),
+            slap_handle: None,
+        })
+    }
+}
+
+/// How the collision object of a `Hitbox` is tested against others
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum QueryType {
+    /// Reports intersections, objects closer than `margin` are tracked
+    Proximity { margin: f32 },
+    /// Reports contacts, which are predicted up to the given distance and angle
+    Contacts {
+        prediction: f32,
+        angular_prediction: f32,
+    },
+}
+
+/// Collision groups of a `Hitbox` as bit masks. Two objects are only tested
+/// against each other if both are members of a group whitelisted by the other
+/// one and neither is a member of a group blacklisted by the other one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollisionLayer {
+    pub membership: u32,
+    pub whitelist: u32,
+    pub blacklist: u32,
+    pub query: QueryType,
+}
+
+impl CollisionLayer {
+    pub const BALLS: u32 = 1;
+    pub const PADDLES: u32 = 1 << 1;
+    /// Walls, goals, bricks, obstacles and power-ups
+    pub const STATIC: u32 = 1 << 2;
+
+    /// Balls hit anything
+    pub fn ball() -> CollisionLayer {
+        CollisionLayer {
+            membership: Self::BALLS,
+            whitelist: Self::BALLS | Self::PADDLES | Self::STATIC,
+            blacklist: 0,
+            query: QueryType::Proximity { margin: 1.0 },
+        }
+    }
+
+    /// Paddles only hit balls
+    pub fn paddle() -> CollisionLayer {
+        CollisionLayer {
+            membership: Self::PADDLES,
+            whitelist: Self::BALLS,
+            blacklist: 0,
+            query: QueryType::Proximity { margin: 1.0 },
+        }
+    }
+
+    /// Static objects only hit balls, so pairs of them are never tested
+    pub fn fixed() -> CollisionLayer {
+        CollisionLayer {
+            membership: Self::STATIC,
+            whitelist: Self::BALLS,
+            blacklist: 0,
+            query: QueryType::Proximity { margin: 1.0 },
+        }
+    }
+
+    pub fn groups(&self) -> ncollide2d::pipeline::CollisionGroups {
+        let indices = |mask: u32| -> Vec<usize> {
+            (0..=ncollide2d::pipeline::CollisionGroups::max_group_id())
+                .filter(|group| mask & (1 << group) != 0)
+                .collect()
+        };
+        ncollide2d::pipeline::CollisionGroups::new()
+            .with_membership(&indices(self.membership))
+            .with_whitelist(&indices(self.whitelist))
+            .with_blacklist(&indices(self.blacklist))
+    }
+
+    pub fn query_type(&self) -> ncollide2d::pipeline::GeometricQueryType<f32> {
+        match self.query {
+            QueryType::Proximity { margin } => {
+                ncollide2d::pipeline::GeometricQueryType::Proximity(margin)
+            }
+            QueryType::Contacts {
+                prediction,
+                angular_prediction,
+            } => ncollide2d::pipeline::GeometricQueryType::Contacts(prediction, angular_prediction),
+        }
+    }
+}
+
+/// Change of the touching state of a pair of entities, ordered by their index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionEvent {
+    Started([legion::entity::Entity; 2]),
+    Stopped([legion::entity::Entity; 2]),
+}
+
+/// Collision events of the last updates of the collision world. Pairs which
+/// keep touching are only found in the `Vec<[Entity; 2]>` resource.
+pub type CollisionEvents = super::events::Events<CollisionEvent>;
+
+impl super::events::Events<CollisionEvent> {
+    /// Pairs which started touching during the current tick
+    pub fn started(&self) -> impl Iterator<Item = &[legion::entity::Entity; 2]> {
+        self.current().filter_map(|event| match event {
+            CollisionEvent::Started(pair) => Some(pair),
+            CollisionEvent::Stopped(_) => None,
+        })
+    }
+
+    /// Pairs which stopped touching during the current tick
+    pub fn stopped(&self) -> impl Iterator<Item = &[legion::entity::Entity; 2]> {
+        self.current().filter_map(|event| match event {
+            CollisionEvent::Stopped(pair) => Some(pair),
+            CollisionEvent::Started(_) => None,
+        })
+    }
+}
+
+/// A ball entered the goal of the player `conceder`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GoalScored {
+    /// Player awarded the point, if any
+    pub scorer: Option<usize>,
+    pub conceder: usize,
+}
+
+/// The player `player` collected a power-up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerUpCollected {
+    pub kind: PowerUpKind,
+    pub player: usize,
+}
+
 pub struct RenderInfo {
     pub shape: Vec<na::Vector2<f32>>,
     pub color: [f32; 3],
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct RenderShape {
     pub color: [f32; 3],
     pub half_extents: na::Vector2<f32>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AiController {
+    /// Ticks until the paddle follows what the AI saw of the ball, which is
+    /// also how often it looks at the ball again
+    pub reaction_ticks: u32,
+    /// Maximum distance the paddle moves per tick
+    pub max_speed: f32,
+    /// Maximum distance the target is randomly offset from the predicted intercept
+    pub aim_error: f32,
+    pub countdown: u32,
+    pub target: Option<f32>,
+    /// Target predicted from the ball as last seen, followed once the countdown ends
+    pub seen: Option<f32>,
+}
+
+impl AiController {
+    pub fn with_difficulty(difficulty: Difficulty) -> AiController {
+        let (reaction_ticks, max_speed, aim_error) = match difficulty {
+            Difficulty::Easy => (1500, 0.03, 35.0),
+            Difficulty::Medium => (500, 0.06, 15.0),
+            Difficulty::Hard => (50, 0.15, 3.0),
+        };
+        AiController {
+            reaction_ticks,
+            max_speed,
+            aim_error,
+            countdown: 0,
+            target: None,
+            seen: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Stay,
+    Up,
+    Down,
+}
+
+/// Moves a paddle according to the action of its `Player` in `Inputs`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputController {
+    pub max_speed: f32,
+}
+
+/// Velocity of a paddle along its long axis
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaddleMotion {
+    pub velocity: f32,
+    /// Velocity the controller of the paddle accelerates it towards
+    pub target: f32,
+}
+
+/// Angular velocity of a ball in radians per tick, which curves its path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Spin {
+    pub angular_velocity: f32,
+}
+
+/// Barrier a ball bounced off last, a ball overlaps a barrier for several frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LastHit {
+    pub barrier: Option<legion::entity::Entity>,
+}
+
+/// Kind of the effect of a `PowerUp`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    BiggerPaddle,
+    FasterBall,
+    SlowOpponent,
+    MultiBall,
+    StickyPaddle,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 5] = [
+        PowerUpKind::BiggerPaddle,
+        PowerUpKind::FasterBall,
+        PowerUpKind::SlowOpponent,
+        PowerUpKind::MultiBall,
+        PowerUpKind::StickyPaddle,
+    ];
+
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            PowerUpKind::BiggerPaddle => [0.0, 1.0, 0.0],
+            PowerUpKind::FasterBall => [1.0, 0.5, 0.0],
+            PowerUpKind::SlowOpponent => [0.0, 0.0, 1.0],
+            PowerUpKind::MultiBall => [1.0, 0.0, 1.0],
+            PowerUpKind::StickyPaddle => [1.0, 1.0, 0.0],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Effect {
+    pub kind: PowerUpKind,
+    /// Ticks until the effect ends
+    pub countdown: u32,
+    /// Change of the affected value, which is taken back once the effect ends
+    pub delta: f32,
+}
+
+/// Timed effects of power-ups on a paddle or ball
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Effects {
+    pub active: Vec<Effect>,
+}
+
+impl Effects {
+    pub fn contains(&self, kind: PowerUpKind) -> bool {
+        self.active.iter().any(|effect| effect.kind == kind)
+    }
+}
+
+/// Ball held by a sticky paddle, which is the `LastHit` of the ball
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stuck {
+    /// Location of the ball relative to the paddle
+    pub offset: na::Vector2<f32>,
+    /// Ticks until the ball is released
+    pub countdown: u32,
+    /// Velocity the ball is released with
+    pub velocity: f32,
+}
+
+/// Hits a `Brick` takes until it is destroyed
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HitPoints {
+    pub remaining: u32,
+}
+
+impl HitPoints {
+    pub fn color(self) -> [f32; 3] {
+        match self.remaining {
+            0 | 1 => [0.0, 1.0, 0.0],
+            2 => [1.0, 1.0, 0.0],
+            3 => [1.0, 0.5, 0.0],
+            _ => [1.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Response of an `Obstacle` to a ball touching it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ObstacleKind {
+    /// Reflects balls along the normal of its rotated side
+    Bar,
+    /// Pushes balls away from its center, speeding them up by `boost`
+    Bumper { boost: f32 },
+    /// Moves balls entering it to `exit`, keeping their direction and velocity
+    Portal { exit: na::Vector2<f32> },
+}
+
+/// Moves an entity along a closed path of waypoints and rotates it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    pub waypoints: Vec<na::Vector2<f32>>,
+    /// Distance travelled per tick
+    pub speed: f32,
+    /// Index of the waypoint the entity is heading to
+    pub next: usize,
+    /// Rotation in radians per tick
+    pub angular_velocity: f32,
+}
+
+pub struct DebugLine {
+    pub from: na::Vector2<f32>,
+    pub to: na::Vector2<f32>,
+    pub color: [f32; 3],
+}
+
+// Resources
+/// Area enclosed by the walls
+pub struct Arena {
+    pub min: na::Vector2<f32>,
+    pub max: na::Vector2<f32>,
+}
+
+pub struct DebugDraw {
+    pub enabled: bool,
+    pub color: [f32; 3],
+    pub intersecting_color: [f32; 3],
+}
+
+/// Seeded pseudo random number generator, the only source of randomness in the simulation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Advances the splitmix64 sequence
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value in `[-1, 1)`
+    pub fn signed(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1 << 23) as f32 - 1.0
+    }
+}
+
+/// Players a match supports at most
+pub const MAX_PLAYERS: usize = 4;
+
+/// Latest action of every player
+pub struct Inputs {
+    pub actions: [Action; MAX_PLAYERS],
+}
+
+pub struct BounceConfig {
+    /// Deflection in radians of a ball striking the outermost edge of a barrier
+    pub max_deflection: f32,
+    /// Fraction of the velocity of a paddle added to a ball bouncing off it
+    pub momentum_transfer: f32,
+    /// Spin of a ball per unit of paddle velocity across its direction
+    pub spin_transfer: f32,
+    /// Factor the spin of a ball keeps per tick
+    pub spin_decay: f32,
+}
+
+/// Change of the velocity of paddles per tick
+pub struct PaddlePhysics {
+    /// Acceleration towards the velocity targeted by the controller
+    pub acceleration: f32,
+    /// Deceleration of a paddle without a target
+    pub friction: f32,
+}
+
+pub struct RallyConfig {
+    pub serve_velocity: f32,
+    /// Factor applied to the ball velocity on every barrier hit
+    pub speed_up: f32,
+    pub max_velocity: f32,
+}
+
+pub struct Rally {
+    pub hits: u32,
+}
+
+/// Extra balls spawned during a rally, a trigger of 0 is disabled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MultiBallConfig {
+    /// Rally hits after which another ball is spawned
+    pub hits_per_ball: u32,
+    /// Ticks after which another ball is spawned
+    pub spawn_ticks: u32,
+    /// Balls in play at most
+    pub max_balls: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MultiBall {
+    /// Balls spawned in the current rally
+    pub spawned: u32,
+    /// Ticks until the next ball is spawned
+    pub countdown: u32,
+    /// Balls requested by power-ups, which are spawned regardless of `max_balls`
+    pub requested: u32,
+}
+
+pub struct PowerUpConfig {
+    /// Ticks between two spawned power-ups, 0 disables them
+    pub spawn_ticks: u32,
+    /// Power-ups in the arena at most
+    pub max_power_ups: usize,
+    pub half_extents: na::Vector2<f32>,
+    /// Ticks an effect lasts
+    pub effect_ticks: u32,
+    /// Ticks a sticky paddle holds a ball
+    pub stick_ticks: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PowerUps {
+    /// Ticks until the next power-up is spawned
+    pub countdown: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameState {
+    Serve,
+    Playing,
+    PointScored,
+    Paused,
+    GameOver,
+}
+
+pub struct MatchConfig {
+    /// Ticks the ball rests at `serve_location` before it is served
+    pub serve_ticks: u32,
+    /// Ticks the game rests after a point was scored
+    pub point_ticks: u32,
+    pub points_to_win: u32,
+    pub win_by: u32,
+    pub serve_location: na::Vector2<f32>,
+    /// Maximum angle in radians a serve deviates from the direction of the receiver
+    pub serve_spread: f32,
+    /// Goals a player concedes until it is eliminated. With 0 no player is
+    /// eliminated and the match is played to `points_to_win` instead.
+    pub lives: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    pub score: [u32; MAX_PLAYERS],
+    /// Goals conceded by every player
+    pub conceded: [u32; MAX_PLAYERS],
+    /// Ticks until the current state advances
+    pub countdown: u32,
+    /// Index of the player the next serve goes to
+    pub receiver: usize,
+    /// State to return to once the game is unpaused
+    pub paused: Option<GameState>,
+}
+
 // Tags
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Barrier;
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Goal;
 #[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wall;
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Ball;
+/// Collectible, which applies the effect of its `PowerUpKind` when a ball touches it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerUp;
+/// Destructible block of the breakout mode, which loses one of its `HitPoints` per hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brick;
+/// Static or animated part of the arena, which responds to balls by its `ObstacleKind`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Obstacle;
@@ -0,0 +1,126 @@
+use super::components;
+use super::raster;
+use super::scene;
+use super::systems;
+use legion::prelude::*;
+use nalgebra as na;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub ball: [f32; 2],
+    /// Distance the ball travels per tick
+    pub ball_velocity: [f32; 2],
+    /// Vertical location of the paddle of every player
+    pub paddles: [f32; 2],
+    pub score: [u32; 2],
+    /// RGB image of the scene, only present if the environment renders pixels
+    pub pixels: Option<Vec<u8>>,
+}
+
+/// Headless match between an agent controlling player 0 and the AI controlling player 1
+pub struct PongEnv {
+    universe: Universe,
+    world: World,
+    resources: Resources,
+    schedule: Schedule,
+    render: Schedule,
+    /// Ticks simulated per step
+    frame_skip: u32,
+    pixels: Option<(usize, usize)>,
+}
+
+impl PongEnv {
+    pub fn new(frame_skip: u32) -> PongEnv {
+        let universe = Universe::new();
+        let world = universe.create_world();
+        let mut env = PongEnv {
+            universe,
+            world,
+            resources: Resources::default(),
+            schedule: scene::build_simulation().flush().build(),
+            render: Schedule::builder()
+                .add_system(systems::build_dispatch_render_system())
+                .flush()
+                .build(),
+            frame_skip,
+            pixels: None,
+        };
+        env.reset(0);
+        env
+    }
+
+    /// Creates an environment, which additionally observes the scene as an
+    /// RGB image of `width` x `height` pixels.
+    pub fn with_pixels(frame_skip: u32, width: usize, height: usize) -> PongEnv {
+        let mut env = Self::new(frame_skip);
+        env.pixels = Some((width, height));
+        env
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.world = self.universe.create_world();
+        self.resources = Resources::default();
+        scene::insert_resources(&mut self.resources, seed);
+        self.resources.insert(Vec::<components::RenderInfo>::new());
+        scene::insert_components(&mut self.world);
+        self.schedule = scene::build_simulation().flush().build();
+        self.observe()
+    }
+
+    /// Simulates `frame_skip` ticks in which the agent performs `action`.
+    /// Returns the observation afterwards, the reward of `1.0` for every point
+    /// the agent scored and `-1.0` for every point it conceded and whether the
+    /// match is over.
+    pub fn step(&mut self, action: components::Action) -> (Observation, f32, bool) {
+        let before = self.resources.get::<components::Match>().unwrap().score;
+        self.resources
+            .get_mut::<components::Inputs>()
+            .unwrap()
+            .actions[0] = action;
+        for _ in 0..self.frame_skip {
+            self.schedule.execute(&mut self.world, &mut self.resources);
+        }
+        let observation = self.observe();
+        let reward =
+            (observation.score[0] - before[0]) as f32 - (observation.score[1] - before[1]) as f32;
+        let done = *self.resources.get::<components::GameState>().unwrap()
+            == components::GameState::GameOver;
+        (observation, reward, done)
+    }
+
+    fn observe(&mut self) -> Observation {
+        let score = self.resources.get::<components::Match>().unwrap().score;
+        let mut observation = Observation {
+            ball: [0.0; 2],
+            ball_velocity: [0.0; 2],
+            paddles: [0.0; 2],
+            score: [score[0], score[1]],
+            pixels: None,
+        };
+        let balls = <(Read<components::Transformation>, Read<components::Velocity>)>::query()
+            .filter(tag::<components::Ball>());
+        if let Some((trans, velocity)) = balls.iter(&self.world).next() {
+            observation.ball = trans.location.into();
+            observation.ball_velocity = [
+                trans.rotation.cos() * velocity.velocity,
+                trans.rotation.sin() * velocity.velocity,
+            ];
+        }
+        let paddles = <(Read<components::Transformation>, Read<components::Player>)>::query()
+            .filter(tag::<components::Barrier>());
+        for (trans, player) in paddles.iter(&self.world) {
+            observation.paddles[player.index] = trans.location[1];
+        }
+        if let Some((width, height)) = self.pixels {
+            self.render.execute(&mut self.world, &mut self.resources);
+            let infos = self.resources.get::<Vec<components::RenderInfo>>().unwrap();
+            observation.pixels = Some(raster::rasterize(
+                &infos,
+                na::Vector2::new(1024.0, 768.0),
+                width,
+                height,
+            ));
+        }
+        observation
+    }
+}
@@ -0,0 +1,90 @@
+/// Events of type `T` sent during the current and the previous tick. Writers
+/// `send` events during a tick and `update` is called once at the start of
+/// every tick, which drops the events of the tick before the previous one.
+/// Every reader keeps its own `EventReader` and sees each event once, as long
+/// as it reads at least every other tick.
+pub struct Events<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    /// Number of events sent before the first one in `previous`
+    previous_start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Events<T> {
+        Events {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Events sent since the last `update`, which are the ones of the current
+    /// tick for systems running after the writer
+    pub fn current(&self) -> std::slice::Iter<'_, T> {
+        self.current.iter()
+    }
+
+    /// Starts a new tick, dropping the events of the tick before the previous one
+    pub fn update(&mut self) {
+        self.previous_start += self.previous.len();
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+
+    /// Drops all events, readers continue with the events sent afterwards
+    pub fn clear(&mut self) {
+        self.previous_start = self.sent();
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    /// Reader which only sees events sent from now on
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader {
+            next: self.sent(),
+            marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Number of events sent in total
+    fn sent(&self) -> usize {
+        self.previous_start + self.previous.len() + self.current.len()
+    }
+}
+
+/// Cursor of a single reader into `Events`. A default reader starts with the
+/// events still kept.
+pub struct EventReader<T> {
+    /// Number of events sent before the next unread one
+    next: usize,
+    marker: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> EventReader<T> {
+        EventReader {
+            next: 0,
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T> EventReader<T> {
+    /// Events sent since the last read, events dropped in between are missed
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let skip = self.next.saturating_sub(events.previous_start);
+        self.next = events.sent();
+        events
+            .previous
+            .iter()
+            .chain(events.current.iter())
+            .skip(skip)
+    }
+}
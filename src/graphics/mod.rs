@@ -138,10 +138,26 @@ pub struct Vertex {
     pub color: [f32; 3],
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrawMode {
+    Triangles,
+    Lines,
+}
+
+impl DrawMode {
+    fn primitive(self) -> u32 {
+        match self {
+            DrawMode::Triangles => gl::TRIANGLES,
+            DrawMode::Lines => gl::LINES,
+        }
+    }
+}
+
 pub struct VertexArray {
     gl: gl::Gl,
     vao: u32,
     vbo: u32,
+    pub mode: DrawMode,
 }
 
 impl VertexArray {
@@ -181,7 +197,12 @@ impl VertexArray {
         println!("Vertex Array Object {}", vao);
         println!("Vertex Buffer Object {}", vbo);
 
-        VertexArray { gl, vao, vbo }
+        VertexArray {
+            gl,
+            vao,
+            vbo,
+            mode: DrawMode::Triangles,
+        }
     }
 
     pub fn with_vertecies(gl: gl::Gl, vertecies: &[Vertex]) -> VertexArray {
@@ -209,7 +230,7 @@ impl VertexArray {
     pub fn draw(&self, vertex_count: i32) {
         unsafe {
             self.gl.BindVertexArray(self.vao);
-            self.gl.DrawArrays(gl::TRIANGLES, 0, vertex_count);
+            self.gl.DrawArrays(self.mode.primitive(), 0, vertex_count);
             self.gl.BindVertexArray(0);
             let error_code = self.gl.GetError();
             match error_code {
@@ -0,0 +1,288 @@
+use nalgebra as na;
+use ncollide2d::bounding_volume::{BoundingVolume, AABB};
+use ncollide2d::pipeline::broad_phase::{
+    BroadPhase, BroadPhaseInterferenceHandler, BroadPhaseProxyHandle,
+};
+use ncollide2d::query::{Ray, RayCast, RayIntersection};
+
+type Cell = [i32; 2];
+
+struct Proxy<T> {
+    data: T,
+    /// Loosened bounding volume, `None` until the proxy is added at an update
+    aabb: Option<AABB<f32>>,
+}
+
+/// Broad phase which hashes bounding volumes into the square cells of a
+/// uniform grid. Only proxies sharing a cell are tested against each other,
+/// which is cheap for many small objects of about the size of a cell. Like
+/// the DBVT broad phase of ncollide, bounding volumes are loosened by a
+/// margin and only moved once they leave their loosened volume.
+pub struct UniformGridBroadPhase<T> {
+    cell_size: f32,
+    margin: f32,
+    proxies: Vec<Option<Proxy<T>>>,
+    /// Slots of removed proxies which are reused
+    free: Vec<usize>,
+    cells: std::collections::HashMap<Cell, Vec<usize>>,
+    /// Interfering proxies, the smaller index first
+    pairs: std::collections::BTreeSet<(usize, usize)>,
+    /// Proxies to move at the next update
+    pending: Vec<(usize, AABB<f32>)>,
+}
+
+impl<T> UniformGridBroadPhase<T> {
+    pub fn new(cell_size: f32, margin: f32) -> UniformGridBroadPhase<T> {
+        assert!(cell_size > 0.0);
+        UniformGridBroadPhase {
+            cell_size,
+            margin,
+            proxies: Vec::new(),
+            free: Vec::new(),
+            cells: std::collections::HashMap::new(),
+            pairs: std::collections::BTreeSet::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Number of interferences detected by this broad phase
+    pub fn num_interferences(&self) -> usize {
+        self.pairs.len()
+    }
+
+    fn cell(&self, point: &ncollide2d::math::Point<f32>) -> Cell {
+        [
+            (point[0] / self.cell_size).floor() as i32,
+            (point[1] / self.cell_size).floor() as i32,
+        ]
+    }
+
+    /// Cells overlapped by `aabb`
+    fn cells_of(&self, aabb: &AABB<f32>) -> impl Iterator<Item = Cell> {
+        let min = self.cell(aabb.mins());
+        let max = self.cell(aabb.maxs());
+        (min[0]..=max[0]).flat_map(move |x| (min[1]..=max[1]).map(move |y| [x, y]))
+    }
+
+    fn unlink(&mut self, id: usize) {
+        let aabb = match self.proxies[id]
+            .as_ref()
+            .and_then(|proxy| proxy.aabb.clone())
+        {
+            Some(aabb) => aabb,
+            None => return,
+        };
+        for cell in self.cells_of(&aabb).collect::<Vec<_>>() {
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    fn link(&mut self, id: usize, aabb: AABB<f32>) {
+        for cell in self.cells_of(&aabb).collect::<Vec<_>>() {
+            self.cells.entry(cell).or_default().push(id);
+        }
+        if let Some(proxy) = &mut self.proxies[id] {
+            proxy.aabb = Some(aabb);
+        }
+    }
+
+    /// Proxies in the cells overlapped by `aabb`, sorted and without duplicates
+    fn candidates(&self, aabb: &AABB<f32>) -> Vec<usize> {
+        let mut candidates: Vec<usize> = self
+            .cells_of(aabb)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+
+    fn get(&self, id: usize) -> Option<(&AABB<f32>, &T)> {
+        added(&self.proxies, id)
+    }
+}
+
+/// Bounding volume and data of the proxy `id`, if it was added at an update
+fn added<T>(proxies: &[Option<Proxy<T>>], id: usize) -> Option<(&AABB<f32>, &T)> {
+    let proxy = proxies.get(id)?.as_ref()?;
+    Some((proxy.aabb.as_ref()?, &proxy.data))
+}
+
+impl<T> BroadPhase<f32, AABB<f32>, T> for UniformGridBroadPhase<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn create_proxy(&mut self, bv: AABB<f32>, data: T) -> BroadPhaseProxyHandle {
+        let proxy = Some(Proxy { data, aabb: None });
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.proxies[id] = proxy;
+                id
+            }
+            None => {
+                self.proxies.push(proxy);
+                self.proxies.len() - 1
+            }
+        };
+        self.pending.push((id, bv));
+        BroadPhaseProxyHandle(id)
+    }
+
+    fn proxy(&self, handle: BroadPhaseProxyHandle) -> Option<(&AABB<f32>, &T)> {
+        self.get(handle.uid())
+    }
+
+    fn remove(&mut self, handles: &[BroadPhaseProxyHandle], handler: &mut dyn FnMut(&T, &T)) {
+        let removed: std::collections::BTreeSet<usize> =
+            handles.iter().map(|handle| handle.uid()).collect();
+        for id in &removed {
+            assert!(
+                matches!(self.proxies.get(*id), Some(Some(_))),
+                "Attempting to remove an object that does not exist."
+            );
+            self.unlink(*id);
+        }
+        let proxies = &self.proxies;
+        self.pairs.retain(|(first, second)| {
+            if removed.contains(first) || removed.contains(second) {
+                if let (Some(first), Some(second)) = (&proxies[*first], &proxies[*second]) {
+                    handler(&first.data, &second.data);
+                }
+                false
+            } else {
+                true
+            }
+        });
+        self.pending.retain(|(id, _)| !removed.contains(id));
+        for id in removed {
+            self.proxies[id] = None;
+            self.free.push(id);
+        }
+    }
+
+    fn deferred_set_bounding_volume(&mut self, handle: BroadPhaseProxyHandle, bv: AABB<f32>) {
+        let proxy = self
+            .proxies
+            .get(handle.uid())
+            .and_then(Option::as_ref)
+            .expect("Attempting to set the bounding volume of an object that does not exist.");
+        if !proxy.aabb.as_ref().is_some_and(|aabb| aabb.contains(&bv)) {
+            self.pending.push((handle.uid(), bv.loosened(self.margin)));
+        }
+    }
+
+    fn deferred_recompute_all_proximities_with(&mut self, handle: BroadPhaseProxyHandle) {
+        if let Some((aabb, _)) = self.get(handle.uid()) {
+            self.pending.push((handle.uid(), aabb.clone()));
+        }
+    }
+
+    fn deferred_recompute_all_proximities(&mut self) {
+        for id in 0..self.proxies.len() {
+            if let Some((aabb, _)) = self.get(id) {
+                self.pending.push((id, aabb.clone()));
+            }
+        }
+    }
+
+    fn update(&mut self, handler: &mut dyn BroadPhaseInterferenceHandler<T>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        // move the proxies first, so every pair sees both final bounding volumes
+        let mut updated = vec![false; self.proxies.len()];
+        for (id, aabb) in std::mem::take(&mut self.pending) {
+            self.unlink(id);
+            self.link(id, aabb);
+            updated[id] = true;
+        }
+        for id in (0..updated.len()).filter(|id| updated[*id]) {
+            let (aabb, data) = added(&self.proxies, id).unwrap();
+            let candidates = self.candidates(aabb);
+            for other in candidates {
+                // pairs of two updated proxies are found by the first one
+                if other == id || (updated[other] && other < id) {
+                    continue;
+                }
+                let (other_aabb, other_data) = added(&self.proxies, other).unwrap();
+                if aabb.intersects(other_aabb) && handler.is_interference_allowed(data, other_data)
+                {
+                    let pair = (id.min(other), id.max(other));
+                    if self.pairs.insert(pair) {
+                        handler.interference_started(data, other_data);
+                    }
+                }
+            }
+        }
+        let proxies = &self.proxies;
+        self.pairs.retain(|(first, second)| {
+            if !updated[*first] && !updated[*second] {
+                return true;
+            }
+            let (first_aabb, first) = added(proxies, *first).unwrap();
+            let (second_aabb, second) = added(proxies, *second).unwrap();
+            let interfering = first_aabb.intersects(second_aabb)
+                && handler.is_interference_allowed(first, second);
+            if !interfering {
+                handler.interference_stopped(first, second);
+            }
+            interfering
+        });
+    }
+
+    fn interferences_with_bounding_volume<'a>(&'a self, bv: &AABB<f32>, out: &mut Vec<&'a T>) {
+        for id in self.candidates(bv) {
+            let (aabb, data) = self.get(id).unwrap();
+            if aabb.intersects(bv) {
+                out.push(data);
+            }
+        }
+    }
+
+    /// Tests every proxy, as rays are rare compared to the pairs of an update
+    fn interferences_with_ray<'a>(&'a self, ray: &Ray<f32>, max_toi: f32, out: &mut Vec<&'a T>) {
+        let identity = na::Isometry2::identity();
+        for id in 0..self.proxies.len() {
+            if let Some((aabb, data)) = self.get(id) {
+                if aabb.intersects_ray(&identity, ray, max_toi) {
+                    out.push(data);
+                }
+            }
+        }
+    }
+
+    fn interferences_with_point<'a>(
+        &'a self,
+        point: &ncollide2d::math::Point<f32>,
+        out: &mut Vec<&'a T>,
+    ) {
+        let ids = self.cells.get(&self.cell(point)).into_iter().flatten();
+        for id in ids {
+            let (aabb, data) = self.get(*id).unwrap();
+            if aabb.contains_local_point(point) {
+                out.push(data);
+            }
+        }
+    }
+
+    fn first_interference_with_ray<'a, 'b>(
+        &'a self,
+        ray: &'b Ray<f32>,
+        max_toi: f32,
+        cost_fn: &'a dyn Fn(T, &'b Ray<f32>, f32) -> Option<(T, RayIntersection<f32>)>,
+    ) -> Option<(T, RayIntersection<f32>)> {
+        let mut candidates = Vec::new();
+        self.interferences_with_ray(ray, max_toi, &mut candidates);
+        candidates
+            .into_iter()
+            .filter_map(|data| cost_fn(data.clone(), ray, max_toi))
+            .min_by(|first, second| first.1.toi.total_cmp(&second.1.toi))
+    }
+}
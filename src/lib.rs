@@ -1,5 +1,19 @@
-mod components;
-mod systems;
+pub mod breakout;
+pub mod collisions;
+pub mod components;
+pub mod env;
+pub mod events;
+pub mod grid;
+pub mod net;
+pub mod options;
+pub mod raster;
+pub mod replay;
+pub mod rollback;
+pub mod scene;
+pub mod snapshot;
+pub mod spectate;
+pub mod systems;
+pub mod tournament;
 
 #[cfg(test)]
 mod tests {
@@ -65,6 +79,7 @@ mod tests {
             Entity,
         >::new());
         resources.insert(Vec::<[Entity; 2]>::new());
+        resources.insert(super::components::CollisionEvents::default());
         let mut world = universe.create_world();
         world.insert(
             (),
@@ -107,9 +122,299 @@ mod tests {
         assert_eq!(resources_len, 1);
     }
 
+    #[test]
+    fn test_events() {
+        use super::events::{EventReader, Events};
+        let read = |reader: &mut EventReader<u32>, events: &Events<u32>| -> Vec<u32> {
+            reader.read(events).copied().collect()
+        };
+        let mut events = Events::default();
+        let mut early = EventReader::default();
+        events.send(1);
+        events.send(2);
+        let mut late = events.reader();
+        events.update();
+        events.send(3);
+        assert_eq!(events.current().copied().collect::<Vec<_>>(), vec![3]);
+        // every reader sees each event once
+        assert_eq!(read(&mut early, &events), vec![1, 2, 3]);
+        assert!(read(&mut early, &events).is_empty());
+        assert_eq!(read(&mut late, &events), vec![3]);
+        // events are dropped by the second update after they were sent
+        events.update();
+        events.send(4);
+        assert_eq!(read(&mut EventReader::default(), &events), vec![3, 4]);
+        events.update();
+        events.update();
+        events.send(5);
+        assert_eq!(read(&mut early, &events), vec![5]);
+        events.clear();
+        events.send(6);
+        assert_eq!(read(&mut late, &events), vec![6]);
+    }
+
+    #[test]
+    fn test_collision_events() {
+        use super::components::{CollisionEvent, CollisionEvents, CollisionLayer, QueryType};
+        let universe = Universe::new();
+        let mut resources = Resources::default();
+        resources.insert(ncollide2d::pipeline::CollisionWorld::<f32, ()>::new(1.0));
+        resources.insert(std::collections::HashMap::<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            Entity,
+        >::new());
+        resources.insert(Vec::<[Entity; 2]>::new());
+        resources.insert(CollisionEvents::default());
+        let mut world = universe.create_world();
+        // one pair reporting proximities and one reporting contacts
+        let contacts = CollisionLayer {
+            query: QueryType::Contacts {
+                prediction: 0.0,
+                angular_prediction: 0.0,
+            },
+            ..CollisionLayer::ball()
+        };
+        let entities = world
+            .insert(
+                (),
+                [
+                    (0.0, CollisionLayer::ball()),
+                    (20.0, CollisionLayer::ball()),
+                    (100.0, contacts),
+                    (120.0, contacts),
+                ]
+                .iter()
+                .map(|(x, layer)| {
+                    (
+                        super::components::Transformation {
+                            location: na::Vector2::new(*x, 0.0),
+                            rotation: 0.0,
+                            scale: na::Vector2::new(1.0, 1.0),
+                        },
+                        super::components::Hitbox {
+                            slap_handle: None,
+                            shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(
+                                na::Vector2::new(5.0_f32, 5.0),
+                            )),
+                        },
+                        *layer,
+                    )
+                }),
+            )
+            .to_vec();
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_events_system::<CollisionEvent>())
+            .add_system(super::systems::build_map_entity_collision_handle_system())
+            .add_system(super::systems::build_collision_system())
+            .flush()
+            .build();
+        let mut step = |world: &mut World, offset: f32| {
+            for index in [1, 3] {
+                world
+                    .get_component_mut::<super::components::Transformation>(entities[index])
+                    .unwrap()
+                    .location[0] = index as f32 * 50.0 - 30.0 + offset;
+            }
+            schedule.execute(world, &mut resources);
+            (
+                resources
+                    .get::<CollisionEvents>()
+                    .unwrap()
+                    .current()
+                    .copied()
+                    .collect::<Vec<_>>(),
+                resources.get::<Vec<[Entity; 2]>>().unwrap().len(),
+            )
+        };
+        let first = [entities[0], entities[1]];
+        let second = [entities[2], entities[3]];
+        assert_eq!(step(&mut world, 0.0), (vec![], 0));
+        assert_eq!(
+            step(&mut world, -12.0),
+            (
+                vec![
+                    CollisionEvent::Started(first),
+                    CollisionEvent::Started(second)
+                ],
+                2
+            )
+        );
+        // touching pairs only start once
+        assert_eq!(step(&mut world, -13.0), (vec![], 2));
+        assert_eq!(
+            step(&mut world, 0.0),
+            (
+                vec![
+                    CollisionEvent::Stopped(first),
+                    CollisionEvent::Stopped(second)
+                ],
+                0
+            )
+        );
+    }
+
+    #[test]
+    fn test_collision_layers() {
+        use super::components::{CollisionLayer, QueryType};
+        let universe = Universe::new();
+        let mut resources = Resources::default();
+        resources.insert(ncollide2d::pipeline::CollisionWorld::<f32, ()>::new(1.0));
+        resources.insert(std::collections::HashMap::<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            Entity,
+        >::new());
+        resources.insert(Vec::<[Entity; 2]>::new());
+        resources.insert(super::components::CollisionEvents::default());
+        let mut world = universe.create_world();
+        let body = |x: f32| {
+            (
+                super::components::Transformation {
+                    location: na::Vector2::new(x, 0.0),
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                super::components::Hitbox {
+                    slap_handle: None,
+                    shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                        5.0_f32, 5.0,
+                    ))),
+                },
+            )
+        };
+        let contacts = QueryType::Contacts {
+            prediction: 1.0,
+            angular_prediction: 0.0,
+        };
+        // a chain of overlapping bodies, neighbours only collide if their layers allow it
+        let layers = [
+            CollisionLayer::fixed(),
+            CollisionLayer::fixed(),
+            CollisionLayer::paddle(),
+            CollisionLayer {
+                query: contacts,
+                ..CollisionLayer::ball()
+            },
+            CollisionLayer {
+                query: contacts,
+                ..CollisionLayer::ball()
+            },
+            CollisionLayer {
+                blacklist: CollisionLayer::BALLS,
+                ..CollisionLayer::ball()
+            },
+        ];
+        let mut entities: Vec<Entity> = world
+            .insert(
+                (),
+                layers.iter().enumerate().map(|(index, layer)| {
+                    let (trans, hitbox) = body(index as f32 * 8.0);
+                    (trans, hitbox, *layer)
+                }),
+            )
+            .to_vec();
+        // bodies without a layer collide with everything
+        entities.extend(world.insert((), vec![body(-8.0)]));
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_map_entity_collision_handle_system())
+            .add_system(super::systems::build_collision_system())
+            .flush()
+            .build();
+        schedule.execute(&mut world, &mut resources);
+        let mut colliders = resources.get::<Vec<[Entity; 2]>>().unwrap().clone();
+        colliders.sort_by_key(|pair| (pair[0].index(), pair[1].index()));
+        assert_eq!(
+            colliders,
+            vec![
+                [entities[0], entities[6]],
+                [entities[2], entities[3]],
+                [entities[3], entities[4]],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debug_draw_system() {
+        let universe = Universe::new();
+        let mut resources = Resources::default();
+        resources.insert(ncollide2d::pipeline::CollisionWorld::<f32, ()>::new(1.0));
+        resources.insert(std::collections::HashMap::<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            Entity,
+        >::new());
+        resources.insert(Vec::<[Entity; 2]>::new());
+        resources.insert(super::components::CollisionEvents::default());
+        resources.insert(Vec::<super::components::DebugLine>::new());
+        resources.insert(super::components::DebugDraw {
+            enabled: true,
+            color: [0.0, 1.0, 0.0],
+            intersecting_color: [1.0, 0.0, 0.0],
+        });
+        let mut world = universe.create_world();
+        world.insert(
+            (),
+            vec![
+                na::Vector2::new(0.0, 0.0),
+                na::Vector2::new(1.0, 2.0),
+                na::Vector2::new(50.0, 50.0),
+            ]
+            .into_iter()
+            .map(|location| {
+                (
+                    super::components::Transformation {
+                        location,
+                        rotation: 0.0,
+                        scale: na::Vector2::new(1.0, 1.0),
+                    },
+                    super::components::Hitbox {
+                        slap_handle: None,
+                        shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(
+                            na::Vector2::new(5.0_f32, 5.0),
+                        )),
+                    },
+                )
+            }),
+        );
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_map_entity_collision_handle_system())
+            .add_system(super::systems::build_collision_system())
+            .add_system(super::systems::build_debug_draw_system())
+            .flush()
+            .build();
+        schedule.execute(&mut world, &mut resources);
+        let lines = resources
+            .get::<Vec<super::components::DebugLine>>()
+            .unwrap();
+        // three outlines and crosses plus one line for the intersecting pair
+        assert_eq!(lines.len(), 3 * 6 + 1);
+        let intersecting = lines
+            .iter()
+            .filter(|line| line.color == [1.0, 0.0, 0.0])
+            .count();
+        assert_eq!(intersecting, 2 * 6 + 1);
+        drop(lines);
+        resources
+            .get_mut::<super::components::DebugDraw>()
+            .unwrap()
+            .enabled = false;
+        schedule.execute(&mut world, &mut resources);
+        assert!(resources
+            .get::<Vec<super::components::DebugLine>>()
+            .unwrap()
+            .is_empty());
+    }
+
     #[derive(Clone, Copy, Debug, PartialEq)]
     struct TestTag;
     struct TestComponent;
+
+    /// Collision events of pairs starting to touch, as reported by the collision system
+    fn started(pairs: Vec<[Entity; 2]>) -> super::components::CollisionEvents {
+        let mut events = super::components::CollisionEvents::default();
+        for pair in pairs {
+            events.send(super::components::CollisionEvent::Started(pair));
+        }
+        events
+    }
     #[test]
     fn test_ball_paddle_collision() {
         let universe = Universe::new();
@@ -140,7 +445,13 @@ mod tests {
         );
         let mut resources = Resources::default();
         let entities = [ball[0], paddle[0]];
-        resources.insert(vec![entities]);
+        resources.insert(started(vec![entities]));
+        resources.insert(super::components::BounceConfig {
+            max_deflection: std::f32::consts::FRAC_PI_4,
+            momentum_transfer: 0.0,
+            spin_transfer: 0.0,
+            spin_decay: 1.0,
+        });
         let mut schedule = Schedule::builder()
             .add_system(super::systems::build_handle_ball_barrier_collision())
             .flush()
@@ -151,4 +462,2250 @@ mod tests {
             .unwrap();
         approx::assert_relative_eq!(trans.rotation, std::f32::consts::PI);
     }
+
+    #[test]
+    fn test_ball_paddle_bounce_angle() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let hitbox = || super::components::Hitbox {
+            slap_handle: None,
+            shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                2.0_f32, 40.0,
+            ))),
+        };
+        let balls = world
+            .insert(
+                (super::components::Ball, ()),
+                vec![na::Vector2::new(95.0, 340.0), na::Vector2::new(95.0, 300.0)]
+                    .into_iter()
+                    .map(|location| {
+                        (super::components::Transformation {
+                            location,
+                            rotation: 0.0,
+                            scale: na::Vector2::new(1.0, 1.0),
+                        },)
+                    }),
+            )
+            .to_vec();
+        let paddle = world.insert(
+            (super::components::Barrier, ()),
+            vec![(
+                super::components::Transformation {
+                    location: na::Vector2::new(100.0, 300.0),
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                hitbox(),
+            )],
+        )[0];
+        let mut resources = Resources::default();
+        resources.insert(started(vec![[balls[0], paddle], [paddle, balls[1]]]));
+        resources.insert(super::components::BounceConfig {
+            max_deflection: std::f32::consts::FRAC_PI_4,
+            momentum_transfer: 0.0,
+            spin_transfer: 0.0,
+            spin_decay: 1.0,
+        });
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_handle_ball_barrier_collision())
+            .flush()
+            .build();
+        schedule.execute(&mut world, &mut resources);
+        let edge = world
+            .get_component::<super::components::Transformation>(balls[0])
+            .unwrap();
+        approx::assert_relative_eq!(
+            edge.rotation,
+            std::f32::consts::PI - std::f32::consts::FRAC_PI_4
+        );
+        let center = world
+            .get_component::<super::components::Transformation>(balls[1])
+            .unwrap();
+        approx::assert_relative_eq!(center.rotation, std::f32::consts::PI);
+    }
+
+    #[test]
+    fn test_paddle_physics() {
+        use super::components::{Action, PaddleMotion, Spin, Transformation, Velocity};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        *resources.get_mut::<super::components::GameState>().unwrap() =
+            super::components::GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let paddle = <Read<super::components::InputController>>::query()
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let start = world
+            .get_component::<Transformation>(paddle)
+            .unwrap()
+            .location[1];
+
+        // the paddle accelerates while the input is held and slides to a halt by friction
+        resources
+            .get_mut::<super::components::Inputs>()
+            .unwrap()
+            .actions[0] = Action::Down;
+        for _ in 0..10 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        let motion = *world.get_component::<PaddleMotion>(paddle).unwrap();
+        approx::assert_relative_eq!(motion.velocity, 0.005);
+        approx::assert_relative_eq!(
+            world
+                .get_component::<Transformation>(paddle)
+                .unwrap()
+                .location[1]
+                - start,
+            0.0275,
+            epsilon = 0.0001
+        );
+        resources
+            .get_mut::<super::components::Inputs>()
+            .unwrap()
+            .actions[0] = Action::Stay;
+        for _ in 0..10 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(
+            world
+                .get_component::<PaddleMotion>(paddle)
+                .unwrap()
+                .velocity,
+            0.0
+        );
+
+        // a ball bouncing off a moving paddle picks up its momentum and spins
+        let mut world = universe.create_world();
+        let ball = world.insert(
+            (super::components::Ball, ()),
+            vec![(
+                Transformation {
+                    location: na::Vector2::new(95.0, 300.0),
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                Velocity { velocity: 0.03 },
+                Spin::default(),
+            )],
+        )[0];
+        let paddle = world.insert(
+            (super::components::Barrier, ()),
+            vec![(
+                Transformation {
+                    location: na::Vector2::new(100.0, 300.0),
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                super::components::Hitbox {
+                    slap_handle: None,
+                    shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                        2.0, 40.0,
+                    ))),
+                },
+                PaddleMotion {
+                    velocity: 0.1,
+                    target: 0.1,
+                },
+            )],
+        )[0];
+        let mut resources = Resources::default();
+        resources.insert(started(vec![[ball, paddle]]));
+        resources.insert(super::components::BounceConfig {
+            max_deflection: std::f32::consts::FRAC_PI_4,
+            momentum_transfer: 0.5,
+            spin_transfer: 0.01,
+            spin_decay: 0.5,
+        });
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_handle_ball_barrier_collision())
+            .flush()
+            .add_system(super::systems::build_spin_system())
+            .flush()
+            .build();
+        schedule.execute(&mut world, &mut resources);
+        let outgoing = na::Vector2::new(-0.03_f32, 0.05);
+        approx::assert_relative_eq!(
+            world.get_component::<Velocity>(ball).unwrap().velocity,
+            outgoing.norm()
+        );
+        // the spin curves the path right away and decays
+        approx::assert_relative_eq!(
+            world
+                .get_component::<Transformation>(ball)
+                .unwrap()
+                .rotation,
+            outgoing[1].atan2(outgoing[0]) - 0.001
+        );
+        approx::assert_relative_eq!(
+            world.get_component::<Spin>(ball).unwrap().angular_velocity,
+            -0.0005
+        );
+        // the ball heads away from the paddle already, so the overlap does not bounce it twice
+        schedule.execute(&mut world, &mut resources);
+        approx::assert_relative_eq!(
+            world.get_component::<Velocity>(ball).unwrap().velocity,
+            outgoing.norm()
+        );
+        approx::assert_relative_eq!(
+            world.get_component::<Spin>(ball).unwrap().angular_velocity,
+            -0.00025
+        );
+    }
+
+    #[test]
+    fn test_fast_ball_does_not_tunnel() {
+        use super::components::{GameState, Transformation, Velocity};
+        for speed in [50.0, 10000.0] {
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            super::scene::insert_resources(&mut resources, 0);
+            super::scene::insert_components(&mut world);
+            *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+            let mut schedule = super::scene::build_simulation().flush().build();
+            let ball = <Read<Velocity>>::query()
+                .filter(tag::<super::components::Ball>())
+                .iter_entities(&world)
+                .next()
+                .unwrap()
+                .0;
+            // the paddle of player 0 is 4 pixels wide, the ball passes it within one tick
+            {
+                let mut trans = world.get_component_mut::<Transformation>(ball).unwrap();
+                trans.location = na::Vector2::new(80.0, 384.0);
+                trans.rotation = std::f32::consts::PI;
+            }
+            world.get_component_mut::<Velocity>(ball).unwrap().velocity = speed;
+            schedule.execute(&mut world, &mut resources);
+
+            let trans = *world.get_component::<Transformation>(ball).unwrap();
+            assert!(trans.location[0] > 50.0 && trans.location[0] < 80.0);
+            assert!(trans.rotation.cos() > 0.0);
+            assert_eq!(*resources.get::<GameState>().unwrap(), GameState::Playing);
+            assert_eq!(resources.get::<super::components::Rally>().unwrap().hits, 1);
+        }
+    }
+
+    #[test]
+    fn test_fast_ball_passes_excluded_objects() {
+        use super::components::{CollisionLayer, GameState, Transformation, Velocity};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let ball = <Read<Velocity>>::query()
+            .filter(tag::<super::components::Ball>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        // the layer of the ball excludes paddles, so the sweep must not stop it at one
+        *world.get_component_mut::<CollisionLayer>(ball).unwrap() = CollisionLayer {
+            blacklist: CollisionLayer::PADDLES,
+            ..CollisionLayer::ball()
+        };
+        {
+            let mut trans = world.get_component_mut::<Transformation>(ball).unwrap();
+            trans.location = na::Vector2::new(80.0, 384.0);
+            trans.rotation = std::f32::consts::PI;
+        }
+        world.get_component_mut::<Velocity>(ball).unwrap().velocity = 50.0;
+        schedule.execute(&mut world, &mut resources);
+
+        let trans = *world.get_component::<Transformation>(ball).unwrap();
+        // it passes the paddle and is only stopped at the goal behind it
+        assert!(trans.location[0] < 50.0);
+        assert!(trans.rotation.cos() < 0.0);
+        assert_eq!(resources.get::<super::components::Rally>().unwrap().hits, 0);
+    }
+
+    #[test]
+    fn test_rally_system() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let ball = world.insert(
+            (super::components::Ball, ()),
+            vec![(
+                super::components::Velocity { velocity: 1.0 },
+                super::components::LastHit { barrier: None },
+            )],
+        )[0];
+        let barriers = world
+            .insert(
+                (super::components::Barrier, ()),
+                vec![(TestComponent,), (TestComponent,)],
+            )
+            .to_vec();
+        let goal = world.insert((super::components::Goal, ()), vec![(TestComponent,)])[0];
+        let mut resources = Resources::default();
+        resources.insert(super::components::CollisionEvents::default());
+        resources.insert(super::components::RallyConfig {
+            serve_velocity: 1.0,
+            speed_up: 1.5,
+            max_velocity: 2.0,
+        });
+        resources.insert(super::components::Rally { hits: 0 });
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_rally_system())
+            .flush()
+            .build();
+        let mut hit = |world: &mut World, other: Entity| {
+            *resources
+                .get_mut::<super::components::CollisionEvents>()
+                .unwrap() = started(vec![[ball, other]]);
+            schedule.execute(world, &mut resources);
+            let hits = resources.get::<super::components::Rally>().unwrap().hits;
+            let velocity = world
+                .get_component::<super::components::Velocity>(ball)
+                .unwrap()
+                .velocity;
+            (hits, velocity)
+        };
+        let (hits, velocity) = hit(&mut world, barriers[0]);
+        assert_eq!(hits, 1);
+        approx::assert_relative_eq!(velocity, 1.5);
+        // hitting the same barrier again counts as well, like the single paddle of breakout
+        let (hits, velocity) = hit(&mut world, barriers[0]);
+        assert_eq!(hits, 2);
+        approx::assert_relative_eq!(velocity, 2.0);
+        let (hits, velocity) = hit(&mut world, barriers[1]);
+        assert_eq!(hits, 3);
+        approx::assert_relative_eq!(velocity, 2.0);
+        // the rally goes on with other balls, it is reset by the next serve
+        let (hits, velocity) = hit(&mut world, goal);
+        assert_eq!(hits, 3);
+        approx::assert_relative_eq!(velocity, 1.0);
+    }
+
+    #[test]
+    fn test_match_state_transitions() {
+        use super::components::GameState;
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let ball = world.insert(
+            (super::components::Ball, ()),
+            vec![(
+                super::components::Transformation {
+                    location: na::Vector2::new(0.0, 0.0),
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                super::components::Velocity { velocity: 5.0 },
+            )],
+        )[0];
+        let goals = world
+            .insert(
+                (super::components::Goal, ()),
+                (0..2).map(|index| {
+                    (
+                        super::components::Player { index },
+                        super::components::Transformation {
+                            location: na::Vector2::new(index as f32 * 20.0, 20.0),
+                            rotation: 0.0,
+                            scale: na::Vector2::new(1.0, 1.0),
+                        },
+                    )
+                }),
+            )
+            .to_vec();
+        let mut resources = Resources::default();
+        resources.insert(super::components::CollisionEvents::default());
+        resources.insert(GameState::Serve);
+        resources.insert(super::components::MatchConfig {
+            serve_ticks: 2,
+            point_ticks: 2,
+            points_to_win: 2,
+            win_by: 2,
+            serve_location: na::Vector2::new(10.0, 20.0),
+            serve_spread: 0.0,
+            lives: 0,
+        });
+        resources.insert(super::components::Rng::new(0));
+        resources.insert(super::components::Match {
+            score: [0; super::components::MAX_PLAYERS],
+            conceded: [0; super::components::MAX_PLAYERS],
+            countdown: 2,
+            receiver: 0,
+            paused: None,
+        });
+        resources.insert(super::components::RallyConfig {
+            serve_velocity: 1.0,
+            speed_up: 1.0,
+            max_velocity: 1.0,
+        });
+        resources.insert(super::components::Rally { hits: 3 });
+        resources.insert(super::components::MultiBallConfig {
+            hits_per_ball: 0,
+            spawn_ticks: 0,
+            max_balls: 1,
+        });
+        resources.insert(super::components::MultiBall {
+            spawned: 0,
+            countdown: 0,
+            requested: 0,
+        });
+        resources.insert(ncollide2d::pipeline::CollisionWorld::<f32, ()>::new(1.0));
+        resources.insert(std::collections::HashMap::<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            Entity,
+        >::new());
+        resources.insert(super::events::Events::<super::components::GoalScored>::default());
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_events_system::<
+                super::components::GoalScored,
+            >())
+            .add_thread_local_fn(super::systems::run_in_states(
+                &[GameState::Playing],
+                vec![
+                    super::systems::build_movement_system(),
+                    super::systems::build_score_system(),
+                ],
+            ))
+            .add_system(super::systems::build_match_system())
+            .flush()
+            .build();
+        let state = |resources: &Resources| *resources.get::<GameState>().unwrap();
+        let location = |world: &World| {
+            world
+                .get_component::<super::components::Transformation>(ball)
+                .unwrap()
+                .location
+        };
+
+        // the ball rests at the serve location during the countdown
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(state(&resources), GameState::Serve);
+        approx::assert_relative_eq!(location(&world), na::Vector2::new(10.0, 20.0));
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(state(&resources), GameState::Playing);
+        assert_eq!(resources.get::<super::components::Rally>().unwrap().hits, 0);
+        schedule.execute(&mut world, &mut resources);
+        approx::assert_relative_eq!(location(&world), na::Vector2::new(9.0, 20.0));
+
+        let mut reader = super::events::EventReader::default();
+        let mut goals_scored = Vec::new();
+        let mut score_on = |world: &mut World, resources: &mut Resources, goal: Entity| {
+            *resources
+                .get_mut::<super::components::CollisionEvents>()
+                .unwrap() = started(vec![[goal, ball]]);
+            *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+            schedule.execute(world, resources);
+            *resources
+                .get_mut::<super::components::CollisionEvents>()
+                .unwrap() = started(Vec::new());
+            let events = resources
+                .get::<super::events::Events<super::components::GoalScored>>()
+                .unwrap();
+            goals_scored.extend(reader.read(&events).copied());
+            let score = resources.get::<super::components::Match>().unwrap().score;
+            [score[0], score[1]]
+        };
+        assert_eq!(score_on(&mut world, &mut resources, goals[0]), [0, 1]);
+        assert_eq!(state(&resources), GameState::PointScored);
+        assert_eq!(score_on(&mut world, &mut resources, goals[1]), [1, 1]);
+        assert_eq!(score_on(&mut world, &mut resources, goals[1]), [2, 1]);
+        // a lead of one point is not enough to win
+        assert_eq!(state(&resources), GameState::PointScored);
+        assert_eq!(
+            resources
+                .get::<super::components::Match>()
+                .unwrap()
+                .receiver,
+            1
+        );
+        assert_eq!(score_on(&mut world, &mut resources, goals[1]), [3, 1]);
+        assert_eq!(state(&resources), GameState::GameOver);
+        let goal = |scorer, conceder| super::components::GoalScored {
+            scorer: Some(scorer),
+            conceder,
+        };
+        assert_eq!(
+            goals_scored,
+            vec![goal(1, 0), goal(0, 1), goal(0, 1), goal(0, 1)]
+        );
+        let game_over_location = location(&world);
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(state(&resources), GameState::GameOver);
+        approx::assert_relative_eq!(location(&world), game_over_location);
+    }
+
+    #[test]
+    fn test_collision_handlers() {
+        use super::components::{Ball, Barrier, Wall};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let balls = world
+            .insert((Ball, ()), vec![(TestComponent,), (TestComponent,)])
+            .to_vec();
+        let paddle = world.insert((Barrier, ()), vec![(TestComponent,)])[0];
+        let wall = world.insert((Wall, ()), vec![(TestComponent,)])[0];
+        let mut resources = Resources::default();
+        resources.insert(Vec::<(&'static str, Entity, Entity)>::new());
+        let log = |name: &'static str| {
+            move |_: &mut World, resources: &mut Resources, first: Entity, second: Entity| {
+                resources
+                    .get_mut::<Vec<(&'static str, Entity, Entity)>>()
+                    .unwrap()
+                    .push((name, first, second));
+            }
+        };
+        let mut handlers = super::collisions::CollisionHandlers::default();
+        handlers
+            .on_collision::<Ball, Barrier, _>(log("barrier"))
+            .on_collision::<Ball, Ball, _>(log("ball"))
+            .on_collision::<Ball, Wall, _>(|world, resources, _, wall| {
+                super::collisions::remove_from_play(world, resources, wall);
+            });
+        handlers.on_collision::<Ball, Wall, _>(log("wall"));
+
+        // pairs are passed in the order of the tags, pairs without a handler are ignored
+        handlers.dispatch(
+            &mut world,
+            &mut resources,
+            &[
+                [paddle, balls[0]],
+                [balls[1], paddle],
+                [balls[0], balls[1]],
+                [paddle, wall],
+                [balls[0], wall],
+            ],
+        );
+        assert_eq!(
+            *resources
+                .get::<Vec<(&'static str, Entity, Entity)>>()
+                .unwrap(),
+            vec![
+                ("barrier", balls[0], paddle),
+                ("barrier", balls[1], paddle),
+                ("ball", balls[0], balls[1]),
+            ]
+        );
+        assert!(!world.is_alive(wall));
+
+        // as a schedule step only pairs starting to touch are dispatched
+        resources
+            .get_mut::<Vec<(&'static str, Entity, Entity)>>()
+            .unwrap()
+            .clear();
+        let mut events = started(vec![[paddle, balls[1]]]);
+        events.send(super::components::CollisionEvent::Stopped([
+            balls[0], paddle,
+        ]));
+        resources.insert(events);
+        let mut schedule = Schedule::builder()
+            .add_thread_local_fn(handlers.into_step())
+            .build();
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(
+            *resources
+                .get::<Vec<(&'static str, Entity, Entity)>>()
+                .unwrap(),
+            vec![("barrier", balls[1], paddle)]
+        );
+    }
+
+    #[test]
+    fn test_remove_from_play() {
+        use super::components::Goal;
+        use ncollide2d::pipeline::{world::CollisionWorld, CollisionObjectSlabHandle};
+        type HandleMap = std::collections::HashMap<CollisionObjectSlabHandle, Entity>;
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        // registers the collision objects, the match is not played yet
+        let mut schedule = super::scene::build_simulation().flush().build();
+        schedule.execute(&mut world, &mut resources);
+        let objects = resources.get::<HandleMap>().unwrap().len();
+        assert_eq!(
+            resources
+                .get::<CollisionWorld<f32, ()>>()
+                .unwrap()
+                .collision_objects()
+                .count(),
+            objects
+        );
+
+        let goal = <Read<super::components::Hitbox>>::query()
+            .filter(tag::<Goal>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        super::collisions::remove_from_play(&mut world, &resources, goal);
+        assert!(!world.is_alive(goal));
+        let map = resources.get::<HandleMap>().unwrap();
+        assert_eq!(map.len(), objects - 1);
+        assert!(!map.values().any(|entity| *entity == goal));
+        assert_eq!(
+            resources
+                .get::<CollisionWorld<f32, ()>>()
+                .unwrap()
+                .collision_objects()
+                .count(),
+            objects - 1
+        );
+    }
+
+    #[test]
+    fn test_uniform_grid_broad_phase() {
+        let simulate = |cell_size: Option<f32>| {
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            super::scene::insert_resources(&mut resources, 5);
+            if let Some(cell_size) = cell_size {
+                super::scene::use_uniform_grid(&mut resources, cell_size);
+            }
+            super::scene::insert_stress(&mut world, &mut resources, 300);
+            let mut schedule = super::scene::build_simulation().flush().build();
+            let mut touching = Vec::new();
+            for _ in 0..300 {
+                schedule.execute(&mut world, &mut resources);
+                touching.push(resources.get::<Vec<[Entity; 2]>>().unwrap().clone());
+            }
+            (touching, super::snapshot::world_hash(&world))
+        };
+        let (dbvt, dbvt_hash) = simulate(None);
+        assert!(dbvt.iter().any(|pairs| !pairs.is_empty()));
+        // the grid finds the same touching pairs every tick, whatever its cell size
+        for cell_size in [8.0, 100.0] {
+            let (grid, grid_hash) = simulate(Some(cell_size));
+            assert_eq!(grid, dbvt);
+            assert_eq!(grid_hash, dbvt_hash);
+        }
+    }
+
+    #[test]
+    fn test_ball_ball_collision() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let balls = world
+            .insert(
+                (super::components::Ball, ()),
+                vec![0.0, 15.0].into_iter().map(|x| {
+                    (super::components::Transformation {
+                        location: na::Vector2::new(x, 0.0),
+                        rotation: 0.0,
+                        scale: na::Vector2::new(1.0, 1.0),
+                    },)
+                }),
+            )
+            .to_vec();
+        let mut resources = Resources::default();
+        resources.insert(started(vec![[balls[0], balls[1]]]));
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_handle_ball_ball_collision())
+            .flush()
+            .build();
+        let rotation = |world: &World, ball: Entity| {
+            world
+                .get_component::<super::components::Transformation>(ball)
+                .unwrap()
+                .rotation
+        };
+        schedule.execute(&mut world, &mut resources);
+        // only the ball catching up is reflected
+        approx::assert_relative_eq!(rotation(&world, balls[0]), std::f32::consts::PI);
+        approx::assert_relative_eq!(rotation(&world, balls[1]), 0.0);
+        // still overlapping
+        schedule.execute(&mut world, &mut resources);
+        approx::assert_relative_eq!(rotation(&world, balls[0]), std::f32::consts::PI);
+        approx::assert_relative_eq!(rotation(&world, balls[1]), 0.0);
+    }
+
+    #[test]
+    fn test_multi_ball() {
+        use super::components::GameState;
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        *resources
+            .get_mut::<super::components::MultiBallConfig>()
+            .unwrap() = super::components::MultiBallConfig {
+            hits_per_ball: 0,
+            spawn_ticks: 10,
+            max_balls: 3,
+        };
+        resources
+            .get_mut::<super::components::Match>()
+            .unwrap()
+            .countdown = 1;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let balls = |world: &World| -> Vec<Entity> {
+            <Read<super::components::Transformation>>::query()
+                .filter(tag::<super::components::Ball>())
+                .iter_entities(world)
+                .map(|(entity, _)| entity)
+                .collect()
+        };
+        let objects = |resources: &Resources| {
+            resources
+                .get::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+                .unwrap()
+                .collision_objects()
+                .count()
+        };
+        // a ball is served every ten ticks until three are in play
+        for _ in 0..40 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(balls(&world).len(), 3);
+        assert_eq!(objects(&resources), 9);
+        // attempts while three balls are in play do not count as served
+        assert_eq!(
+            resources
+                .get::<super::components::MultiBall>()
+                .unwrap()
+                .spawned,
+            2
+        );
+
+        let mut score_with = |world: &mut World, resources: &mut Resources, ball: Entity| {
+            world
+                .get_component_mut::<super::components::Transformation>(ball)
+                .unwrap()
+                .location = na::Vector2::new(20.0, 200.0);
+            schedule.execute(world, resources);
+            let score = resources.get::<super::components::Match>().unwrap().score;
+            ([score[0], score[1]], *resources.get::<GameState>().unwrap())
+        };
+        let in_play = balls(&world);
+        assert_eq!(
+            score_with(&mut world, &mut resources, in_play[0]),
+            ([0, 1], GameState::Playing)
+        );
+        assert_eq!(balls(&world).len(), 2);
+        assert_eq!(objects(&resources), 8);
+        assert_eq!(
+            score_with(&mut world, &mut resources, in_play[1]),
+            ([0, 2], GameState::Playing)
+        );
+        assert_eq!(objects(&resources), 7);
+        // the last ball ends the point
+        assert_eq!(
+            score_with(&mut world, &mut resources, in_play[2]),
+            ([0, 3], GameState::PointScored)
+        );
+        assert_eq!(balls(&world), vec![in_play[2]]);
+        assert_eq!(objects(&resources), 7);
+    }
+
+    #[test]
+    fn test_power_ups() {
+        use super::components::{GameState, PowerUpKind};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        resources
+            .get_mut::<super::components::PowerUpConfig>()
+            .unwrap()
+            .effect_ticks = 5;
+        *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let paddle = <Read<super::components::Player>>::query()
+            .filter(tag::<super::components::Barrier>())
+            .iter_entities(&world)
+            .find(|(_, player)| player.index == 0)
+            .unwrap()
+            .0;
+        let ball = <Read<super::components::Velocity>>::query()
+            .filter(tag::<super::components::Ball>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let location = na::Vector2::new(325.0, 200.0);
+        let power_up = world.insert(
+            (super::components::PowerUp, ()),
+            vec![(
+                super::components::Transformation {
+                    location,
+                    rotation: 0.0,
+                    scale: na::Vector2::new(1.0, 1.0),
+                },
+                super::components::Hitbox {
+                    shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                        8.0, 8.0,
+                    ))),
+                    slap_handle: None,
+                },
+                PowerUpKind::BiggerPaddle,
+            )],
+        )[0];
+        world
+            .get_component_mut::<super::components::Transformation>(ball)
+            .unwrap()
+            .location = location;
+        world
+            .get_component_mut::<super::components::LastHit>(ball)
+            .unwrap()
+            .barrier = Some(paddle);
+        let lengths = |world: &World, resources: &Resources| {
+            let hitbox = world
+                .get_component::<super::components::Hitbox>(paddle)
+                .unwrap();
+            let co_world = resources
+                .get::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+                .unwrap();
+            let co = co_world.objects.get(hitbox.slap_handle.unwrap()).unwrap();
+            [
+                world
+                    .get_component::<super::components::RenderShape>(paddle)
+                    .unwrap()
+                    .half_extents[1],
+                hitbox.shape.local_aabb().half_extents()[1],
+                co.shape().local_aabb().half_extents()[1],
+            ]
+        };
+
+        // the paddle of the player who hit the ball last grows
+        schedule.execute(&mut world, &mut resources);
+        assert!(!world.is_alive(power_up));
+        assert_eq!(
+            resources
+                .get::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+                .unwrap()
+                .collision_objects()
+                .count(),
+            7
+        );
+        assert_eq!(lengths(&world, &resources), [60.0; 3]);
+        assert_eq!(
+            resources
+                .get::<super::events::Events<super::components::PowerUpCollected>>()
+                .unwrap()
+                .current()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![super::components::PowerUpCollected {
+                kind: PowerUpKind::BiggerPaddle,
+                player: 0,
+            }]
+        );
+        // and shrinks back once the effect ended
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(lengths(&world, &resources), [60.0; 3]);
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(lengths(&world, &resources), [40.0; 3]);
+
+        // power-ups of random kinds are spawned in the arena
+        resources
+            .get_mut::<super::components::PowerUpConfig>()
+            .unwrap()
+            .spawn_ticks = 10;
+        for _ in 0..25 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        let spawned: Vec<PowerUpKind> = <Read<PowerUpKind>>::query()
+            .filter(tag::<super::components::PowerUp>())
+            .iter(&world)
+            .map(|kind| *kind)
+            .collect();
+        assert_eq!(spawned.len(), 2);
+    }
+
+    #[test]
+    fn test_power_up_effects() {
+        use super::components::{
+            AiController, GameState, InputController, PowerUpKind, Stuck, Velocity,
+        };
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        {
+            let mut config = resources
+                .get_mut::<super::components::PowerUpConfig>()
+                .unwrap();
+            config.effect_ticks = 5;
+            config.stick_ticks = 3;
+        }
+        *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let paddles: Vec<Entity> = (0..2)
+            .map(|index| {
+                <Read<super::components::Player>>::query()
+                    .filter(tag::<super::components::Barrier>())
+                    .iter_entities(&world)
+                    .find(|(_, player)| player.index == index)
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        let balls = |world: &World| -> Vec<Entity> {
+            <Read<Velocity>>::query()
+                .filter(tag::<super::components::Ball>())
+                .iter_entities(world)
+                .map(|(entity, _)| entity)
+                .collect()
+        };
+        let ball = balls(&world)[0];
+        let velocity = |world: &World| world.get_component::<Velocity>(ball).unwrap().velocity;
+        let set_velocity = |world: &mut World, velocity: f32| {
+            world.get_component_mut::<Velocity>(ball).unwrap().velocity = velocity;
+        };
+        let move_ball = |world: &mut World, location: na::Vector2<f32>| {
+            world
+                .get_component_mut::<super::components::Transformation>(ball)
+                .unwrap()
+                .location = location;
+        };
+        // the ball collects a power-up on behalf of player 0 in the next tick
+        let offer = |world: &mut World, kind: PowerUpKind| {
+            let location = na::Vector2::new(325.0, 200.0);
+            move_ball(world, location);
+            world
+                .get_component_mut::<super::components::LastHit>(ball)
+                .unwrap()
+                .barrier = Some(paddles[0]);
+            world.insert(
+                (super::components::PowerUp, ()),
+                vec![(
+                    super::components::Transformation {
+                        location,
+                        rotation: 0.0,
+                        scale: na::Vector2::new(1.0, 1.0),
+                    },
+                    super::components::Hitbox {
+                        shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(
+                            na::Vector2::new(8.0, 8.0),
+                        )),
+                        slap_handle: None,
+                    },
+                    kind,
+                )],
+            );
+        };
+        let mut run = |world: &mut World, resources: &mut Resources, ticks: usize| {
+            for _ in 0..ticks {
+                schedule.execute(world, resources);
+            }
+        };
+
+        // a faster ball keeps speed gained during the effect once it ends
+        offer(&mut world, PowerUpKind::FasterBall);
+        run(&mut world, &mut resources, 1);
+        approx::assert_relative_eq!(velocity(&world), 0.045);
+        set_velocity(&mut world, 0.1);
+        run(&mut world, &mut resources, 4);
+        approx::assert_relative_eq!(velocity(&world), 0.085);
+        // and is not sped up beyond the maximum velocity
+        offer(&mut world, PowerUpKind::FasterBall);
+        run(&mut world, &mut resources, 1);
+        approx::assert_relative_eq!(velocity(&world), 0.12);
+        run(&mut world, &mut resources, 4);
+        approx::assert_relative_eq!(velocity(&world), 0.085);
+
+        // only the opponents are slowed down
+        let ai_speed = |world: &World| {
+            world
+                .get_component::<AiController>(paddles[1])
+                .unwrap()
+                .max_speed
+        };
+        let input_speed = |world: &World| {
+            world
+                .get_component::<InputController>(paddles[0])
+                .unwrap()
+                .max_speed
+        };
+        let (ai_before, input_before) = (ai_speed(&world), input_speed(&world));
+        offer(&mut world, PowerUpKind::SlowOpponent);
+        run(&mut world, &mut resources, 1);
+        approx::assert_relative_eq!(ai_speed(&world), ai_before * 0.5);
+        approx::assert_relative_eq!(input_speed(&world), input_before);
+        run(&mut world, &mut resources, 4);
+        approx::assert_relative_eq!(ai_speed(&world), ai_before);
+
+        // the ball is served once more, even beyond the maximum number of balls
+        offer(&mut world, PowerUpKind::MultiBall);
+        run(&mut world, &mut resources, 1);
+        assert_eq!(balls(&world).len(), 2);
+        assert_eq!(
+            resources
+                .get::<super::components::MultiBall>()
+                .unwrap()
+                .requested,
+            0
+        );
+
+        // a sticky paddle holds the next ball hitting it
+        offer(&mut world, PowerUpKind::StickyPaddle);
+        run(&mut world, &mut resources, 1);
+        world
+            .get_component_mut::<super::components::LastHit>(ball)
+            .unwrap()
+            .barrier = None;
+        move_ball(&mut world, na::Vector2::new(58.0, 384.0));
+        run(&mut world, &mut resources, 1);
+        let stuck = *world.get_component::<Stuck>(ball).unwrap();
+        assert_eq!(velocity(&world), 0.0);
+        assert!(stuck.velocity > 0.0);
+        run(&mut world, &mut resources, 2);
+        assert_eq!(velocity(&world), 0.0);
+        // and releases it with the velocity it arrived with
+        run(&mut world, &mut resources, 1);
+        assert!(world.get_component::<Stuck>(ball).is_none());
+        assert_eq!(velocity(&world), stuck.velocity);
+    }
+
+    #[test]
+    fn test_four_player_elimination() {
+        use super::components::GameState;
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_players(&mut world, 4);
+        resources
+            .get_mut::<super::components::MatchConfig>()
+            .unwrap()
+            .lives = 2;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let entities = |world: &World, player: usize| -> Vec<Entity> {
+            <Read<super::components::Player>>::query()
+                .iter_entities(world)
+                .filter(|(_, one_player)| one_player.index == player)
+                .map(|(entity, _)| entity)
+                .collect()
+        };
+        let ball = <Read<super::components::Velocity>>::query()
+            .filter(tag::<super::components::Ball>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let paddle = entities(&world, 0)
+            .into_iter()
+            .find(|entity| {
+                world
+                    .get_tag::<super::components::Barrier>(*entity)
+                    .is_some()
+            })
+            .unwrap();
+        // top, bottom and right goal with the ball hit by player 0
+        let goals = [
+            na::Vector2::new(100.0, 10.0),
+            na::Vector2::new(100.0, 758.0),
+            na::Vector2::new(622.0, 200.0),
+        ];
+        let mut play = |world: &mut World, resources: &mut Resources, location, rotation| {
+            // the ball leaves the goal it scored in before, like it does when it is served
+            for location in [na::Vector2::new(325.0, 384.0), location] {
+                {
+                    let mut trans = world
+                        .get_component_mut::<super::components::Transformation>(ball)
+                        .unwrap();
+                    trans.location = location;
+                    trans.rotation = rotation;
+                }
+                world
+                    .get_component_mut::<super::components::LastHit>(ball)
+                    .unwrap()
+                    .barrier = Some(paddle);
+                *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+                schedule.execute(world, resources);
+            }
+            *resources.get::<GameState>().unwrap()
+        };
+        let objects = |resources: &Resources| {
+            resources
+                .get::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+                .unwrap()
+                .collision_objects()
+                .count()
+        };
+
+        assert_eq!(
+            play(&mut world, &mut resources, goals[0], 0.0),
+            GameState::PointScored
+        );
+        let game = *resources.get::<super::components::Match>().unwrap();
+        assert_eq!(game.score, [1, 0, 0, 0]);
+        assert_eq!(game.conceded, [0, 0, 1, 0]);
+        assert_eq!(objects(&resources), 9);
+        // the second goal eliminates the player on the top
+        assert_eq!(
+            play(&mut world, &mut resources, goals[0], 0.0),
+            GameState::PointScored
+        );
+        let game = *resources.get::<super::components::Match>().unwrap();
+        assert_eq!(game.score, [2, 0, 0, 0]);
+        assert_eq!(game.receiver, 3);
+        let eliminated = entities(&world, 2);
+        assert_eq!(eliminated.len(), 1);
+        assert!(world
+            .get_tag::<super::components::Wall>(eliminated[0])
+            .is_some());
+        assert!(world
+            .get_tag::<super::components::Goal>(eliminated[0])
+            .is_none());
+        assert_eq!(objects(&resources), 8);
+        // its goal reflects balls from now on
+        let up = -std::f32::consts::FRAC_PI_2;
+        assert_eq!(
+            play(&mut world, &mut resources, goals[0], up),
+            GameState::Playing
+        );
+        approx::assert_relative_eq!(
+            world
+                .get_component::<super::components::Transformation>(ball)
+                .unwrap()
+                .rotation,
+            std::f32::consts::FRAC_PI_2
+        );
+
+        for _ in 0..2 {
+            play(&mut world, &mut resources, goals[1], 0.0);
+        }
+        assert_eq!(
+            play(&mut world, &mut resources, goals[2], 0.0),
+            GameState::PointScored
+        );
+        // the last player standing wins
+        assert_eq!(
+            play(&mut world, &mut resources, goals[2], 0.0),
+            GameState::GameOver
+        );
+        assert_eq!(
+            resources.get::<super::components::Match>().unwrap().score,
+            [6, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_breakout_paddle_hits() {
+        use super::components::{GameState, Transformation, Velocity};
+        let options =
+            super::options::Options::from_args(std::iter::once("--breakout".into())).unwrap();
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::setup(&options, 0, &mut world, &mut resources).unwrap();
+        *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let ball = <Read<Velocity>>::query()
+            .filter(tag::<super::components::Ball>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let place = |world: &mut World, y: f32| {
+            let mut trans = world.get_component_mut::<Transformation>(ball).unwrap();
+            trans.location = na::Vector2::new(325.0, y);
+            trans.rotation = std::f32::consts::FRAC_PI_2;
+        };
+
+        // the single paddle is hit again and again, every hit speeds up the ball
+        for _ in 0..3 {
+            place(&mut world, 600.0);
+            schedule.execute(&mut world, &mut resources);
+            place(&mut world, 724.0);
+            schedule.execute(&mut world, &mut resources);
+            let trans = *world.get_component::<Transformation>(ball).unwrap();
+            assert!(trans.rotation.sin() < 0.0);
+        }
+        assert_eq!(resources.get::<super::components::Rally>().unwrap().hits, 3);
+        approx::assert_relative_eq!(
+            world.get_component::<Velocity>(ball).unwrap().velocity,
+            0.03 * 1.1 * 1.1 * 1.1,
+            epsilon = 0.00001
+        );
+    }
+
+    #[test]
+    fn test_breakout() {
+        use super::components::GameState;
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::breakout::insert_resources(&mut resources);
+        let layout = super::breakout::Layout::parse(
+            r#"{"rows": ["2.1"], "brick_half_extents": [20.0, 8.0], "top": 100.0, "gap": 4.0}"#,
+        )
+        .unwrap();
+        super::breakout::insert_components(&mut world, &layout);
+        *resources.get_mut::<GameState>().unwrap() = GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let bricks: Vec<(Entity, na::Vector2<f32>)> =
+            <Read<super::components::Transformation>>::query()
+                .filter(tag::<super::components::Brick>())
+                .iter_entities(&world)
+                .map(|(entity, trans)| (entity, trans.location))
+                .collect();
+        assert_eq!(bricks.len(), 2);
+        let paddle = <Read<super::components::Player>>::query()
+            .filter(tag::<super::components::Barrier>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let ball = <Read<super::components::Velocity>>::query()
+            .filter(tag::<super::components::Ball>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        world
+            .get_component_mut::<super::components::LastHit>(ball)
+            .unwrap()
+            .barrier = Some(paddle);
+        let hit = |world: &mut World, location: na::Vector2<f32>| {
+            let mut trans = world
+                .get_component_mut::<super::components::Transformation>(ball)
+                .unwrap();
+            trans.location = location + na::Vector2::new(0.0, 17.0);
+            trans.rotation = -std::f32::consts::FRAC_PI_2;
+        };
+        let objects = |resources: &Resources| {
+            resources
+                .get::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+                .unwrap()
+                .collision_objects()
+                .count()
+        };
+        let (brick, location) = bricks
+            .iter()
+            .copied()
+            .find(|(brick, _)| {
+                world
+                    .get_component::<super::components::HitPoints>(*brick)
+                    .unwrap()
+                    .remaining
+                    == 2
+            })
+            .unwrap();
+
+        // a hit bounces the ball back and damages the brick once while they overlap
+        hit(&mut world, location);
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        let trans = *world
+            .get_component::<super::components::Transformation>(ball)
+            .unwrap();
+        assert!(trans.rotation.sin() > 0.0);
+        assert_eq!(
+            world
+                .get_component::<super::components::HitPoints>(brick)
+                .unwrap()
+                .remaining,
+            1
+        );
+        assert_eq!(objects(&resources), 8);
+
+        // the last hit point destroys the brick and scores for the last hitter, once the
+        // ball left it and hits it again
+        hit(&mut world, location + na::Vector2::new(0.0, 40.0));
+        schedule.execute(&mut world, &mut resources);
+        hit(&mut world, location);
+        schedule.execute(&mut world, &mut resources);
+        assert!(!world.is_alive(brick));
+        assert_eq!(objects(&resources), 7);
+        assert_eq!(
+            resources.get::<super::components::Match>().unwrap().score[0],
+            1
+        );
+        assert_eq!(*resources.get::<GameState>().unwrap(), GameState::Playing);
+
+        // clearing all bricks ends the match
+        let (last, location) = bricks
+            .iter()
+            .copied()
+            .find(|(other, _)| *other != brick)
+            .unwrap();
+        hit(&mut world, location);
+        schedule.execute(&mut world, &mut resources);
+        assert!(!world.is_alive(last));
+        assert_eq!(
+            resources.get::<super::components::Match>().unwrap().score[0],
+            2
+        );
+        assert_eq!(*resources.get::<GameState>().unwrap(), GameState::GameOver);
+    }
+
+    #[test]
+    fn test_obstacles() {
+        use super::components::{Animation, ObstacleKind, Transformation, Velocity};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        *resources.get_mut::<super::components::GameState>().unwrap() =
+            super::components::GameState::Playing;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let exit = na::Vector2::new(100.0, 100.0);
+        let obstacles = world
+            .insert(
+                (super::components::Obstacle, ()),
+                vec![
+                    super::scene::obstacle(
+                        ObstacleKind::Bumper { boost: 2.0 },
+                        na::Vector2::new(325.0, 200.0),
+                        std::sync::Arc::new(ncollide2d::shape::Ball::new(15.0)),
+                        Animation::default(),
+                    ),
+                    super::scene::obstacle(
+                        ObstacleKind::Portal { exit },
+                        na::Vector2::new(150.0, 600.0),
+                        std::sync::Arc::new(ncollide2d::shape::Ball::new(12.0)),
+                        Animation::default(),
+                    ),
+                    super::scene::obstacle(
+                        ObstacleKind::Bar,
+                        na::Vector2::new(500.0, 300.0),
+                        std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                            40.0, 3.0,
+                        ))),
+                        Animation::default(),
+                    ),
+                    super::scene::obstacle(
+                        ObstacleKind::Bar,
+                        na::Vector2::new(325.0, 700.0),
+                        std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                            40.0, 3.0,
+                        ))),
+                        Animation {
+                            waypoints: vec![
+                                na::Vector2::new(330.0, 700.0),
+                                na::Vector2::new(325.0, 700.0),
+                            ],
+                            speed: 2.0,
+                            next: 0,
+                            angular_velocity: 0.1,
+                        },
+                    ),
+                ],
+            )
+            .to_vec();
+        world
+            .get_component_mut::<Transformation>(obstacles[2])
+            .unwrap()
+            .rotation = std::f32::consts::FRAC_PI_4;
+        let diagonal = na::Vector2::new(-1.0, 1.0).normalize();
+        let balls = world
+            .insert(
+                (super::components::Ball, ()),
+                [
+                    na::Vector2::new(325.0, 224.0),
+                    na::Vector2::new(145.0, 600.0),
+                    na::Vector2::new(500.0, 300.0) + diagonal * 12.0,
+                ]
+                .iter()
+                .map(|location| {
+                    let (mut trans, shape, velocity, hitbox, last_hit, effects, spin, layer) =
+                        super::scene::ball();
+                    trans.location = *location;
+                    (
+                        trans, shape, velocity, hitbox, last_hit, effects, spin, layer,
+                    )
+                }),
+            )
+            .to_vec();
+        let heading = |world: &mut World, ball: Entity, rotation: f32| {
+            world
+                .get_component_mut::<Transformation>(ball)
+                .unwrap()
+                .rotation = rotation;
+        };
+        heading(&mut world, balls[0], -std::f32::consts::FRAC_PI_2);
+        heading(&mut world, balls[1], 0.0);
+        heading(&mut world, balls[2], -std::f32::consts::FRAC_PI_2);
+        schedule.execute(&mut world, &mut resources);
+        let trans =
+            |world: &World, entity: Entity| *world.get_component::<Transformation>(entity).unwrap();
+        let velocity =
+            |world: &World, ball: Entity| world.get_component::<Velocity>(ball).unwrap().velocity;
+
+        // a bumper pushes the ball back and speeds it up
+        approx::assert_relative_eq!(
+            trans(&world, balls[0]).rotation,
+            std::f32::consts::FRAC_PI_2,
+            epsilon = 0.001
+        );
+        approx::assert_relative_eq!(velocity(&world, balls[0]), 0.06);
+        // a portal keeps the direction and velocity of the ball
+        assert_eq!(trans(&world, balls[1]).location, exit);
+        assert_eq!(trans(&world, balls[1]).rotation, 0.0);
+        approx::assert_relative_eq!(velocity(&world, balls[1]), 0.03);
+        // a bar reflects along its rotated normal
+        approx::assert_relative_eq!(
+            trans(&world, balls[2]).rotation.abs(),
+            std::f32::consts::PI,
+            epsilon = 0.001
+        );
+
+        // animated obstacles rotate and follow their waypoints in a loop
+        approx::assert_relative_eq!(trans(&world, obstacles[3]).rotation, 0.1);
+        approx::assert_relative_eq!(trans(&world, obstacles[3]).location[0], 327.0);
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        approx::assert_relative_eq!(trans(&world, obstacles[3]).location[0], 328.0);
+        approx::assert_relative_eq!(trans(&world, obstacles[3]).rotation, 0.4);
+        // the bumper only counts the first frame of an overlap
+        approx::assert_relative_eq!(velocity(&world, balls[0]), 0.06);
+    }
+
+    #[test]
+    fn test_predict_intercept() {
+        let arena = super::components::Arena {
+            min: na::Vector2::new(0.0, 0.0),
+            max: na::Vector2::new(100.0, 100.0),
+        };
+        let straight = super::systems::predict_intercept(
+            na::Vector2::new(50.0, 50.0),
+            na::Vector2::new(1.0, 0.5),
+            na::Vector2::zeros(),
+            80.0,
+            &arena,
+        );
+        approx::assert_relative_eq!(straight, 65.0);
+        // bounces off the lower wall at y = 100 once and off the upper one at y = 0 once
+        let bounced = super::systems::predict_intercept(
+            na::Vector2::new(50.0, 50.0),
+            na::Vector2::new(-1.0, 2.0),
+            na::Vector2::zeros(),
+            0.0,
+            &arena,
+        );
+        approx::assert_relative_eq!(bounced, 50.0);
+        let bounced = super::systems::predict_intercept(
+            na::Vector2::new(50.0, 50.0),
+            na::Vector2::new(-1.0, 1.5),
+            na::Vector2::zeros(),
+            0.0,
+            &arena,
+        );
+        approx::assert_relative_eq!(bounced, 75.0);
+        // crossing a horizontal line bounces off the left and right bounds
+        let crossing = super::systems::predict_crossing(
+            na::Vector2::new(50.0, 50.0),
+            na::Vector2::new(1.5, -1.0),
+            na::Vector2::zeros(),
+            1,
+            0.0,
+            &arena,
+        );
+        approx::assert_relative_eq!(crossing, 75.0);
+        // the edge of a ball bounces, which shrinks the bounds by its half extents
+        let bounced = super::systems::predict_intercept(
+            na::Vector2::new(50.0, 50.0),
+            na::Vector2::new(-1.0, 1.5),
+            na::Vector2::new(10.0, 10.0),
+            0.0,
+            &arena,
+        );
+        approx::assert_relative_eq!(bounced, 55.0);
+    }
+
+    #[test]
+    fn test_ai_reaction_delay() {
+        use super::components::{AiController, Difficulty, Player, Transformation, Velocity};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        let ball = <Read<Velocity>>::query()
+            .filter(tag::<super::components::Ball>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let paddle = <Read<Player>>::query()
+            .filter(component::<AiController>())
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let mut ai = AiController::with_difficulty(Difficulty::Hard);
+        ai.reaction_ticks = 3;
+        ai.aim_error = 0.0;
+        *world.get_component_mut::<AiController>(paddle).unwrap() = ai;
+        let move_ball = |world: &mut World, y: f32| {
+            let mut trans = world.get_component_mut::<Transformation>(ball).unwrap();
+            trans.location = na::Vector2::new(512.0, y);
+            trans.rotation = 0.0;
+        };
+        move_ball(&mut world, 300.0);
+        world.get_component_mut::<Velocity>(ball).unwrap().velocity = 1.0;
+        let mut schedule = Schedule::builder()
+            .add_system(super::systems::build_ai_system())
+            .build();
+        let target = |world: &World| world.get_component::<AiController>(paddle).unwrap().target;
+
+        // the ball is seen at the first tick, but only followed three ticks later
+        schedule.execute(&mut world, &mut resources);
+        move_ball(&mut world, 500.0);
+        for _ in 0..2 {
+            schedule.execute(&mut world, &mut resources);
+            assert_eq!(target(&world), None);
+        }
+        schedule.execute(&mut world, &mut resources);
+        approx::assert_relative_eq!(target(&world).unwrap(), 300.0);
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        approx::assert_relative_eq!(target(&world).unwrap(), 500.0);
+    }
+
+    #[test]
+    fn test_ai_versus_ai() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 0);
+        super::scene::insert_components(&mut world);
+        let query =
+            <Read<super::components::Player>>::query().filter(tag::<super::components::Barrier>());
+        let paddles: Vec<Entity> = query
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .collect();
+        assert_eq!(paddles.len(), 2);
+        // fast forward the match, the ball and paddles move ten times faster than usual
+        for paddle in paddles {
+            let mut ai = super::components::AiController::with_difficulty(
+                super::components::Difficulty::Hard,
+            );
+            ai.max_speed *= 10.0;
+            ai.reaction_ticks /= 10;
+            // a paddle follows a single controller
+            let _ = world.remove_component::<super::components::InputController>(paddle);
+            world.add_component(paddle, ai).unwrap();
+        }
+        {
+            let mut rally = resources
+                .get_mut::<super::components::RallyConfig>()
+                .unwrap();
+            rally.serve_velocity *= 10.0;
+            rally.max_velocity *= 10.0;
+            // accelerations scale with the square of the time
+            let mut physics = resources
+                .get_mut::<super::components::PaddlePhysics>()
+                .unwrap();
+            physics.acceleration *= 100.0;
+            physics.friction *= 100.0;
+            resources
+                .get_mut::<super::components::Match>()
+                .unwrap()
+                .countdown = 1;
+        }
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let mut longest_rally = 0;
+        for _ in 0..15000 {
+            schedule.execute(&mut world, &mut resources);
+            longest_rally =
+                longest_rally.max(resources.get::<super::components::Rally>().unwrap().hits);
+        }
+        assert!(longest_rally >= 10);
+    }
+
+    #[test]
+    fn test_tournament() {
+        use super::components::Difficulty;
+        use super::tournament::{Config, Entrant, Format};
+        let entrants = vec![
+            Entrant::with_difficulty("easy", Difficulty::Easy),
+            Entrant::with_difficulty("medium", Difficulty::Medium),
+            Entrant::with_difficulty("hard", Difficulty::Hard),
+        ];
+        // matches end before the first serve, so all of them are drawn
+        let config = Config {
+            format: Format::RoundRobin,
+            games: 2,
+            threads: 3,
+            seed: 5,
+            max_ticks: 100,
+            points_to_win: 11,
+        };
+        let report = super::tournament::run(&entrants, &config);
+        assert_eq!(report.matches.len(), 6);
+        assert_eq!(
+            report,
+            super::tournament::run(
+                &entrants,
+                &Config {
+                    threads: 1,
+                    ..config.clone()
+                }
+            )
+        );
+        assert_eq!(
+            report
+                .matches
+                .iter()
+                .map(|result| result.seed)
+                .collect::<Vec<_>>(),
+            (5..11).collect::<Vec<_>>()
+        );
+        assert_eq!((report.matches[1].left, report.matches[1].right), (1, 0));
+        for standing in &report.standings {
+            assert_eq!((standing.played, standing.draws), (4, 4));
+            assert_eq!(standing.win_rate, 0.5);
+            assert!(standing.confidence[0] < 0.5 && standing.confidence[1] > 0.5);
+        }
+        let mut csv = Vec::new();
+        report.write_standings_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 4);
+        let mut csv = Vec::new();
+        report.write_matches_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 7);
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            serde_json::from_str::<super::tournament::Report>(&json).unwrap(),
+            report
+        );
+
+        // drawn pairings advance the better seed, the odd entrant gets a bye
+        let report = super::tournament::run(
+            &entrants,
+            &Config {
+                format: Format::Elimination,
+                games: 1,
+                ..config
+            },
+        );
+        assert_eq!(report.matches.len(), 2);
+        assert_eq!((report.matches[1].left, report.matches[1].right), (0, 2));
+        assert_eq!(report.champion, Some("easy".to_string()));
+
+        let interval = super::tournament::wilson_interval(5.0, 10);
+        approx::assert_relative_eq!(interval[0], 0.2366, epsilon = 0.0001);
+        approx::assert_relative_eq!(interval[1], 0.7634, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn test_tournament_standings() {
+        use super::components::Difficulty;
+        use super::tournament::{Entrant, MatchResult};
+        let entrants = vec![
+            Entrant::with_difficulty("a", Difficulty::Easy),
+            Entrant::with_difficulty("b", Difficulty::Easy),
+            Entrant::with_difficulty("c", Difficulty::Easy),
+        ];
+        let result = |left: usize, right: usize, score: [u32; 2]| MatchResult {
+            round: 0,
+            seed: 0,
+            left,
+            right,
+            score,
+            ticks: 0,
+            longest_rally: 0,
+            winner: match score[0].cmp(&score[1]) {
+                std::cmp::Ordering::Greater => Some(left),
+                std::cmp::Ordering::Less => Some(right),
+                std::cmp::Ordering::Equal => None,
+            },
+        };
+        let matches = vec![
+            result(0, 1, [3, 1]),
+            result(1, 0, [2, 2]),
+            result(2, 0, [5, 0]),
+            result(1, 2, [4, 1]),
+        ];
+        // a and b are level on wins and draws, b has the better point difference
+        let standings = super::tournament::standings(&entrants, &matches);
+        let summary: Vec<(&str, u32, u32, u32, u32, u32)> = standings
+            .iter()
+            .map(|standing| {
+                (
+                    standing.name.as_str(),
+                    standing.wins,
+                    standing.draws,
+                    standing.losses,
+                    standing.points_for,
+                    standing.points_against,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("b", 1, 1, 1, 7, 6),
+                ("a", 1, 1, 1, 5, 8),
+                ("c", 1, 0, 1, 6, 4),
+            ]
+        );
+        approx::assert_relative_eq!(standings[0].win_rate, 0.5);
+
+        // more wins advance, whichever side of the pairing
+        let pairing = [matches[0].clone(), matches[1].clone()];
+        assert_eq!(super::tournament::advancing(&pairing, 0, 1), 0);
+        assert_eq!(super::tournament::advancing(&pairing, 1, 0), 0);
+        // level wins are decided by the point difference
+        let pairing = [result(0, 1, [1, 3]), result(1, 0, [0, 4])];
+        assert_eq!(super::tournament::advancing(&pairing, 0, 1), 0);
+        assert_eq!(super::tournament::advancing(&pairing, 1, 0), 0);
+    }
+
+    #[test]
+    fn test_env_is_deterministic() {
+        use super::components::Action;
+        let actions = [Action::Up, Action::Stay, Action::Down, Action::Down];
+        let mut envs = [super::env::PongEnv::new(200), super::env::PongEnv::new(200)];
+        let observations: Vec<Vec<_>> = envs
+            .iter_mut()
+            .map(|env| {
+                let first = env.reset(42);
+                let mut observations = vec![first.clone()];
+                for action in actions.iter().cycle().take(20) {
+                    let (observation, reward, done) = env.step(*action);
+                    assert_eq!(reward, 0.0);
+                    assert!(!done);
+                    observations.push(observation);
+                }
+                assert_ne!(observations.last().unwrap(), &first);
+                observations
+            })
+            .collect();
+        assert_eq!(observations[0], observations[1]);
+    }
+
+    #[test]
+    fn test_env_pixels() {
+        let mut env = super::env::PongEnv::with_pixels(1, 256, 192);
+        let observation = env.reset(0);
+        let pixels = observation.pixels.unwrap();
+        assert_eq!(pixels.len(), 256 * 192 * 3);
+        let ball = (observation.ball[1] as usize / 4 * 256 + observation.ball[0] as usize / 4) * 3;
+        assert_eq!(pixels[ball..ball + 3], [255, 0, 0]);
+        assert_eq!(pixels[0..3], [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_identical_simulations_hash_equal() {
+        let simulate = |seed: u64| {
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            super::scene::insert_resources(&mut resources, seed);
+            super::scene::insert_components(&mut world);
+            resources
+                .get_mut::<super::components::Match>()
+                .unwrap()
+                .countdown = 1;
+            let mut schedule = super::scene::build_simulation().flush().build();
+            (0..3000)
+                .map(|_| {
+                    schedule.execute(&mut world, &mut resources);
+                    super::snapshot::world_hash(&world)
+                })
+                .collect::<Vec<u64>>()
+        };
+        let hashes = simulate(3);
+        assert_eq!(hashes, simulate(3));
+        // the serve direction depends on the seed
+        assert_ne!(hashes.last(), simulate(4).last());
+    }
+
+    #[test]
+    fn test_replay_reproduces_match() {
+        use super::components::Action;
+        use super::options::Options;
+        let new_match = |options: &Options, seed: u64| {
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            super::scene::setup(options, seed, &mut world, &mut resources).unwrap();
+            resources
+                .get_mut::<super::components::Match>()
+                .unwrap()
+                .countdown = 1;
+            (universe, world, resources)
+        };
+        let options = Options::from_args(
+            [
+                "--multi-ball",
+                "--power-ups",
+                "--players",
+                "2",
+                "--grid",
+                "8",
+            ]
+            .iter()
+            .map(|arg| arg.to_string()),
+        )
+        .unwrap();
+        let (_universe, mut world, mut resources) = new_match(&options, 5);
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let settings = super::replay::Settings::of(&options);
+        let mut recording =
+            super::replay::Replay::new(5, settings, super::snapshot::world_hash(&world));
+        for tick in 0..3000 {
+            resources
+                .get_mut::<super::components::Inputs>()
+                .unwrap()
+                .actions[0] = [Action::Up, Action::Stay, Action::Down][tick / 700 % 3];
+            recording.record(&resources);
+            schedule.execute(&mut world, &mut resources);
+        }
+        recording.final_hash = super::snapshot::world_hash(&world);
+        assert_eq!(recording.ticks(), 3000);
+
+        let mut bytes = Vec::new();
+        recording.write(&mut bytes).unwrap();
+        let replay = super::replay::Replay::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(replay, recording);
+        assert!(super::replay::Replay::read(&mut &bytes[1..]).is_err());
+
+        // the settings come from the replay, not from the options of the playback
+        let mut options = Options::default();
+        replay.settings.apply(&mut options);
+        assert_eq!(super::replay::Settings::of(&options), settings);
+        let (_universe, mut world, mut resources) = new_match(&options, replay.seed);
+        assert_eq!(super::snapshot::world_hash(&world), replay.scene_hash);
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let mut playback = super::replay::Playback::new(replay);
+        while playback.apply(&mut resources) {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(playback.verify(&world));
+    }
+
+    #[test]
+    fn test_options_from_args() {
+        let args = |args: &[&str]| {
+            super::options::Options::from_args(args.iter().map(|arg| arg.to_string()))
+        };
+        let options = args(&["--seed", "7", "--replay", "match.replay"]).unwrap();
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.replay, Some("match.replay".into()));
+        assert_eq!(options.record, None);
+        assert!(!options.multi_ball);
+        assert!(args(&["--multi-ball"]).unwrap().multi_ball);
+        assert!(args(&["--power-ups"]).unwrap().power_ups);
+        assert!(args(&["--obstacles"]).unwrap().obstacles);
+        assert!(args(&["--layout", "bricks.json"]).unwrap().breakout);
+        assert!(args(&["--breakout", "--players", "2"]).is_err());
+        assert!(args(&["--host", "127.0.0.1:4000", "--multi-ball"]).is_err());
+        assert!(args(&["--spectate", "127.0.0.1:4000", "--power-ups"]).is_err());
+        assert!(args(&["--broadcast", "127.0.0.1:4000", "--obstacles"]).is_err());
+        assert!(args(&["--broadcast", "127.0.0.1:4000", "--breakout"]).is_err());
+        assert!(args(&["--broadcast", "127.0.0.1:4000", "--players", "4"]).is_err());
+        assert_eq!(args(&["--stress", "500"]).unwrap().stress, Some(500));
+        assert!(args(&["--stress", "500", "--breakout"]).is_err());
+        assert_eq!(args(&["--grid", "8"]).unwrap().grid, Some(8.0));
+        assert!(args(&["--grid", "0"]).is_err());
+        assert!(args(&["--grid", "0.001"]).is_err());
+        assert_eq!(args(&["--grid", "1"]).unwrap().grid, Some(1.0));
+        let peer = ["--bind", "0.0.0.0:5000", "--peer", "10.0.0.2:5000"];
+        let options = args(&[&peer[..], &["--player", "1", "--seed", "3"]].concat()).unwrap();
+        assert_eq!(options.player, Some(1));
+        assert!(args(&[&peer[..], &["--seed", "3"]].concat()).is_err());
+        assert!(args(&[&peer[..], &["--player", "2", "--seed", "3"]].concat()).is_err());
+        assert!(args(&[&peer[..], &["--player", "0"]].concat()).is_err());
+        assert!(args(&["--player", "0", "--seed", "3"]).is_err());
+        assert!(args(&["--seed"]).is_err());
+        assert!(args(&["--seed", "x"]).is_err());
+        assert!(args(&["--speed"]).is_err());
+    }
+
+    #[test]
+    fn test_scene_setup() {
+        let setup = |args: &[&str]| {
+            let options =
+                super::options::Options::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            let players = super::scene::setup(&options, 3, &mut world, &mut resources).unwrap();
+            let paddles = <Read<super::components::Player>>::query()
+                .filter(tag::<super::components::Barrier>())
+                .iter(&world)
+                .count();
+            (players, paddles, resources)
+        };
+        let (players, paddles, resources) = setup(&["--players", "4", "--multi-ball"]);
+        assert_eq!((players, paddles), (4, 4));
+        assert_eq!(
+            resources
+                .get::<super::components::MatchConfig>()
+                .unwrap()
+                .lives,
+            super::scene::LIVES
+        );
+        assert_eq!(
+            *resources
+                .get::<super::components::MultiBallConfig>()
+                .unwrap(),
+            super::scene::MULTI_BALL
+        );
+        assert_eq!(setup(&["--breakout"]).0, 1);
+        let (players, paddles, _) = setup(&["--stress", "10"]);
+        assert_eq!((players, paddles), (0, 0));
+    }
+
+    #[test]
+    fn test_snapshot_restores_simulation() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 9);
+        super::scene::insert_components(&mut world);
+        resources
+            .get_mut::<super::components::Match>()
+            .unwrap()
+            .countdown = 1;
+        let mut schedule = super::scene::build_simulation().flush().build();
+        for _ in 0..2000 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        let saved = super::snapshot::world_hash(&world);
+        let snapshot = super::snapshot::Snapshot::capture(&world, &resources);
+        let path =
+            std::env::temp_dir().join(format!("ecs-pong-snapshot-{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        for _ in 0..2000 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        let expected = super::snapshot::world_hash(&world);
+
+        // loading into the running world replaces all entities and collision objects
+        let snapshot = super::snapshot::Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        snapshot.restore(&mut world, &mut resources);
+        assert_eq!(super::snapshot::world_hash(&world), saved);
+        assert_eq!(
+            <Read<super::components::Hitbox>>::query()
+                .iter(&world)
+                .filter(|hitbox| hitbox.slap_handle.is_some())
+                .count(),
+            0
+        );
+        for _ in 0..2000 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(super::snapshot::world_hash(&world), expected);
+        let co_world = resources
+            .get::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+            .unwrap();
+        assert_eq!(co_world.collision_objects().count(), 7);
+    }
+
+    #[test]
+    fn test_net_message_roundtrip() {
+        use super::net::{Message, NetTransform};
+        let messages = vec![
+            Message::Join {
+                scene_hash: 0x0123_4567_89ab_cdef,
+            },
+            Message::Accept { player: 1 },
+            Message::Reject,
+            Message::Input {
+                sequence: 7,
+                action: super::components::Action::Down,
+            },
+            Message::State {
+                tick: 300,
+                score: [3, 5, 0, 0],
+                transforms: vec![
+                    NetTransform {
+                        location: [1.0, 2.0],
+                        rotation: 3.0,
+                    },
+                    NetTransform {
+                        location: [-4.5, 0.25],
+                        rotation: 0.0,
+                    },
+                ],
+            },
+        ];
+        for one_message in messages {
+            let bytes = one_message.encode();
+            assert_eq!(Message::decode(&bytes).unwrap(), one_message);
+            assert!(Message::decode(&bytes[..bytes.len() - 1]).is_err() || bytes.len() == 4);
+            let mut other_version = bytes.clone();
+            other_version[2] += 1;
+            assert!(Message::decode(&other_version).is_err());
+        }
+    }
+
+    #[test]
+    fn test_net_loopback() {
+        let new_match = || {
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            super::scene::insert_resources(&mut resources, 11);
+            super::scene::insert_components(&mut world);
+            resources
+                .get_mut::<super::components::Match>()
+                .unwrap()
+                .countdown = 1;
+            (universe, world, resources)
+        };
+        let (_host_universe, mut host_world, mut host_resources) = new_match();
+        let (_client_universe, mut client_world, _) = new_match();
+        let scene_hash = super::snapshot::world_hash(&host_world);
+        super::net::control_remotely(&mut host_world, 1, 0.1);
+        let mut schedule = super::scene::build_simulation().flush().build();
+        let localhost = "127.0.0.1:0".parse().unwrap();
+        let mut host = super::net::Host::bind(localhost, 1, scene_hash, 10).unwrap();
+        let mut client =
+            super::net::Client::connect(localhost, host.local_addr().unwrap(), scene_hash, 20)
+                .unwrap();
+
+        // a client with a different scene is rejected
+        let mut other =
+            super::net::Client::connect(localhost, host.local_addr().unwrap(), !scene_hash, 20)
+                .unwrap();
+        let mut rejected = None;
+        for _ in 0..1000 {
+            host.receive(&mut host_resources).unwrap();
+            if let Err(err) = other.receive() {
+                rejected = Some(err.kind());
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(rejected, Some(std::io::ErrorKind::InvalidData));
+        assert_eq!(other.player(), None);
+
+        for tick in 0..3000 {
+            host.receive(&mut host_resources).unwrap();
+            schedule.execute(&mut host_world, &mut host_resources);
+            host.send_state(&host_world, &host_resources).unwrap();
+            client.receive().unwrap();
+            if tick % 10 == 0 {
+                client.send_input(super::components::Action::Up).unwrap();
+            }
+            client.interpolate(&mut client_world);
+        }
+        assert_eq!(client.player(), Some(1));
+        assert!(host.client().is_some());
+
+        let locations = |world: &World| {
+            super::net::synced_entities(world)
+                .into_iter()
+                .map(|entity| {
+                    world
+                        .get_component::<super::components::Transformation>(entity)
+                        .unwrap()
+                        .location
+                })
+                .collect::<Vec<na::Vector2<f32>>>()
+        };
+        let host_locations = locations(&host_world);
+        let client_locations = locations(&client_world);
+        // the remote player moved its paddle up
+        let paddle = <(
+            Read<super::components::Transformation>,
+            Read<super::components::Player>,
+        )>::query()
+        .filter(tag::<super::components::Barrier>())
+        .iter(&host_world)
+        .find(|(_, player)| player.index == 1)
+        .map(|(trans, _)| trans.location[1])
+        .unwrap();
+        assert!(paddle < 300.0);
+        // the client lags behind the host by its interpolation delay only
+        for (host_location, client_location) in host_locations.iter().zip(client_locations.iter()) {
+            assert!((host_location - client_location).norm() < 5.0);
+        }
+    }
+
+    /// Delays and drops the datagrams sent over a socket
+    struct LossyLink {
+        socket: std::net::UdpSocket,
+        rng: super::components::Rng,
+        /// Ticks a datagram is delayed
+        latency: u32,
+        /// Probability a datagram is lost
+        loss: f32,
+        clock: u32,
+        in_flight: std::collections::VecDeque<(u32, Vec<u8>)>,
+    }
+
+    impl super::rollback::Transport for LossyLink {
+        fn send(&mut self, datagram: &[u8]) -> std::io::Result<()> {
+            if (self.rng.signed() + 1.0) / 2.0 >= self.loss {
+                self.in_flight
+                    .push_back((self.clock + self.latency, datagram.to_vec()));
+            }
+            while self
+                .in_flight
+                .front()
+                .is_some_and(|(due, _)| *due <= self.clock)
+            {
+                let (_, datagram) = self.in_flight.pop_front().unwrap();
+                self.socket.send(&datagram)?;
+            }
+            Ok(())
+        }
+
+        fn receive(&mut self, buffer: &mut [u8]) -> std::io::Result<Option<usize>> {
+            super::rollback::Transport::receive(&mut self.socket, buffer)
+        }
+    }
+
+    #[test]
+    fn test_rollback_with_latency_and_loss() {
+        use super::components::Action;
+        let ticks = 2000;
+        let action = |player: usize, tick: u32| {
+            [Action::Up, Action::Stay, Action::Down][((tick / 150) as usize + player) % 3]
+        };
+        let new_match = || {
+            let universe = Universe::new();
+            let mut world = universe.create_world();
+            let mut resources = Resources::default();
+            super::scene::insert_resources(&mut resources, 13);
+            super::scene::insert_components(&mut world);
+            super::net::control_remotely(&mut world, 1, 0.1);
+            resources
+                .get_mut::<super::components::Match>()
+                .unwrap()
+                .countdown = 1;
+            let schedule = super::scene::build_simulation().flush().build();
+            (universe, world, resources, schedule)
+        };
+
+        let (_universe, mut world, mut resources, mut schedule) = new_match();
+        for tick in 0..ticks {
+            {
+                let mut inputs = resources.get_mut::<super::components::Inputs>().unwrap();
+                inputs.actions[0] = action(0, tick);
+                inputs.actions[1] = action(1, tick);
+            }
+            schedule.execute(&mut world, &mut resources);
+        }
+        let expected = super::snapshot::world_hash(&world);
+
+        let sockets = [
+            std::net::UdpSocket::bind("127.0.0.1:0").unwrap(),
+            std::net::UdpSocket::bind("127.0.0.1:0").unwrap(),
+        ];
+        let mut peers = Vec::new();
+        for (player, socket) in sockets.iter().enumerate() {
+            socket
+                .connect(sockets[1 - player].local_addr().unwrap())
+                .unwrap();
+            socket.set_nonblocking(true).unwrap();
+            let link = LossyLink {
+                socket: socket.try_clone().unwrap(),
+                rng: super::components::Rng::new(player as u64),
+                latency: 6,
+                loss: 0.2,
+                clock: 0,
+                in_flight: std::collections::VecDeque::new(),
+            };
+            let session = super::rollback::Session::new(link, player, 20);
+            peers.push((session, new_match()));
+        }
+        for _ in 0..4 * ticks {
+            for (player, (session, (_, world, resources, schedule))) in peers.iter_mut().enumerate()
+            {
+                session.transport_mut().clock += 1;
+                if session.tick() < ticks {
+                    let local = action(player, session.tick());
+                    session.advance(world, resources, schedule, local).unwrap();
+                } else {
+                    session.sync(world, resources, schedule).unwrap();
+                }
+            }
+            if peers
+                .iter()
+                .all(|(session, _)| session.confirmed() == ticks)
+            {
+                break;
+            }
+        }
+        for (session, (_, world, _, _)) in &peers {
+            assert_eq!(session.confirmed(), ticks);
+            assert!(session.rollbacks() > 0);
+            assert_eq!(super::snapshot::world_hash(world), expected);
+        }
+    }
+
+    #[test]
+    fn test_spectators_follow_broadcast() {
+        use super::spectate::{Broadcaster, Frame, Spectator};
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut resources = Resources::default();
+        super::scene::insert_resources(&mut resources, 17);
+        super::scene::insert_components(&mut world);
+        resources
+            .get_mut::<super::components::Match>()
+            .unwrap()
+            .countdown = 1;
+        let mut schedule = super::scene::build_simulation().flush().build();
+
+        let mut broadcaster = Broadcaster::bind("127.0.0.1:0".parse().unwrap(), 10).unwrap();
+        let addr = broadcaster.local_addr().unwrap();
+        let mut spectators = vec![Spectator::connect(addr).unwrap()];
+        let mut previous = Frame::capture(&world, &resources, 0);
+        for tick in 1..=3000 {
+            schedule.execute(&mut world, &mut resources);
+            broadcaster.broadcast(&world, &resources).unwrap();
+            for one_spectator in &mut spectators {
+                assert!(one_spectator.receive().unwrap());
+            }
+            if tick == 1500 {
+                // only the ball moved, walls and goals are left out of a delta
+                let frame = Frame::capture(&world, &resources, tick);
+                assert!(frame.encode(Some(&previous)).len() < frame.encode(None).len());
+                assert_eq!(
+                    Frame::decode(&frame.encode(Some(&previous)), Some(&previous)).unwrap(),
+                    frame
+                );
+                spectators.push(Spectator::connect(addr).unwrap());
+            }
+            if tick % 10 == 0 {
+                previous = Frame::capture(&world, &resources, tick);
+            }
+        }
+        assert_eq!(broadcaster.spectators(), 2);
+
+        let expected = Frame::capture(&world, &resources, 3000);
+        for one_spectator in &mut spectators {
+            for _ in 0..1000 {
+                if one_spectator.frame() == Some(&expected) {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                one_spectator.receive().unwrap();
+            }
+            assert_eq!(one_spectator.frame(), Some(&expected));
+            let mut spectator_world = universe.create_world();
+            super::scene::insert_components(&mut spectator_world);
+            one_spectator.frame().unwrap().apply(&mut spectator_world);
+            assert_eq!(Frame::capture(&spectator_world, &resources, 3000), expected);
+        }
+
+        // a message which can not be decoded closes the stream instead of being retried
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut spectator = Spectator::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        std::io::Write::write_all(&mut stream, &[4, 0, 0, 0, b'P', b'S', 0, 0]).unwrap();
+        let mut failed = false;
+        for _ in 0..1000 {
+            if spectator.receive().is_err() {
+                failed = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(failed);
+        assert!(!spectator.receive().unwrap());
+    }
 }
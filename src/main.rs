@@ -4,7 +4,10 @@
 #![allow(clippy::type_complexity)]
 
 use glutin::dpi;
+use glutin::event::ElementState;
 use glutin::event::Event;
+use glutin::event::KeyboardInput;
+use glutin::event::VirtualKeyCode;
 use glutin::event::WindowEvent;
 use glutin::event_loop::ControlFlow;
 use glutin::event_loop::EventLoop;
@@ -14,12 +17,51 @@ use graphics::Program;
 use legion::prelude::*;
 use nalgebra as na;
 use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+use ecs_pong::net;
+use ecs_pong::options::Options;
+use ecs_pong::replay::{Playback, Replay, Settings};
+use ecs_pong::rollback;
+use ecs_pong::spectate;
+use ecs_pong::{components, scene, snapshot, systems};
 
-mod components;
 mod graphics;
-mod systems;
+
+/// Simulated time of one tick of the schedule
+const TICK: Duration = Duration::from_micros(200);
+/// Ticks simulated at most per frame before the simulation falls behind
+const MAX_TICKS_PER_FRAME: u32 = 500;
+/// File the match is saved to with F5 and loaded from with F9
+const QUICK_SAVE: &str = "quicksave.json";
+/// Ticks between two datagrams sent to the other end of a networked match
+const NET_SEND_INTERVAL: u32 = 50;
+/// Ticks a peer to peer match runs ahead of the remote peer at most
+const MAX_ROLLBACK: u32 = 250;
+/// Ticks between two frames streamed to spectators
+const BROADCAST_INTERVAL: u32 = 50;
 
 fn main() {
+    let mut options = Options::from_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!(
+            "usage: ecs-pong [--seed <n>] [--record <file>] [--replay <file>]
+                [--host <addr>] [--connect <addr>] [--bind <addr> --peer <addr> --player <0|1>]
+                [--broadcast <addr>] [--spectate <addr>] [--players <n>] [--multi-ball]
+                [--power-ups] [--obstacles] [--breakout] [--layout <file>] [--stress <balls>]
+                [--grid <cell size>]"
+        );
+        std::process::exit(2);
+    });
+    let mut playback = options
+        .replay
+        .as_ref()
+        .map(|path| Playback::new(Replay::load(path).expect("Failed to load replay")));
+    // a replay is simulated with the settings it was recorded with
+    if let Some(playback) = &playback {
+        playback.replay().settings.apply(&mut options);
+    }
+
     let el = EventLoop::new();
     let wb = window::WindowBuilder::new()
         .with_title("Hello world!")
@@ -53,30 +95,174 @@ fn main() {
         ],
     );
 
+    let mut debug_vertecies = graphics::VertexArray::new(gl.clone());
+    debug_vertecies.mode = graphics::DrawMode::Lines;
+
     let universe = Universe::new();
     let mut world = universe.create_world();
     let mut resources = Resources::default();
     resources.insert(Vec::<components::RenderInfo>::new());
-    resources.insert(ncollide2d::pipeline::CollisionWorld::<f32, ()>::new(1.0));
-    resources.insert(std::collections::HashMap::<
-        ncollide2d::pipeline::CollisionObjectSlabHandle,
-        Entity,
-    >::new());
-    resources.insert(Vec::<[Entity; 2]>::new());
-    let mut schedule = Schedule::builder()
-        .add_system(systems::build_map_entity_collision_handle_system())
-        .add_system(systems::build_movement_system())
-        .add_system(systems::build_collision_system())
-        .add_system(systems::build_handle_ball_barrier_collision())
+    let seed = match (&playback, options.seed) {
+        (Some(playback), _) => playback.replay().seed,
+        (None, Some(seed)) => seed,
+        (None, None) => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    };
+    scene::setup(&options, seed, &mut world, &mut resources).expect("Failed to set up scene");
+    resources.insert(Vec::<components::DebugLine>::new());
+    resources.insert(components::DebugDraw {
+        enabled: false,
+        color: [0.0, 1.0, 0.0],
+        intersecting_color: [1.0, 1.0, 0.0],
+    });
+    let mut schedule = scene::build_simulation().flush().build();
+    let mut render = Schedule::builder()
         .add_system(systems::build_dispatch_render_system())
+        .add_system(systems::build_debug_draw_system())
         .flush()
         .build();
 
-    insert_components(&mut world);
+    let scene_hash = snapshot::world_hash(&world);
+    if let Some(playback) = &playback {
+        if playback.replay().scene_hash != scene_hash {
+            eprintln!("Replay was recorded in a different scene");
+            std::process::exit(1);
+        }
+    }
+    let mut recording = options
+        .record
+        .as_ref()
+        .map(|_| Replay::new(seed, Settings::of(&options), scene_hash));
+    let mut host = options.host.map(|addr| {
+        net::control_remotely(&mut world, 1, 0.1);
+        net::Host::bind(addr, 1, scene_hash, NET_SEND_INTERVAL).expect("Failed to host match")
+    });
+    let mut client = options.connect.map(|addr| {
+        let bind = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        net::Client::connect(
+            bind.parse().unwrap(),
+            addr,
+            scene_hash,
+            2 * NET_SEND_INTERVAL,
+        )
+        .expect("Failed to connect to host")
+    });
+    let mut session = options.bind.zip(options.peer).map(|(bind, peer)| {
+        let local_player = options.player.unwrap();
+        net::control_remotely(&mut world, 1 - local_player, 0.1);
+        let socket = std::net::UdpSocket::bind(bind).expect("Failed to bind socket");
+        socket.connect(peer).expect("Failed to connect to peer");
+        socket.set_nonblocking(true).unwrap();
+        rollback::Session::new(socket, local_player, MAX_ROLLBACK)
+    });
+    let mut broadcaster = options.broadcast.map(|addr| {
+        spectate::Broadcaster::bind(addr, BROADCAST_INTERVAL).expect("Failed to start broadcast")
+    });
+    let mut spectator = options
+        .spectate
+        .map(|addr| spectate::Spectator::connect(addr).expect("Failed to connect to broadcast"));
+    let networked = host.is_some() || client.is_some() || session.is_some() || spectator.is_some();
+    let mut net_ticks = 0;
+    let mut local_action = components::Action::Stay;
+    let mut replaying = playback.is_some();
+    let mut last_frame = Instant::now();
+    let mut lag = Duration::default();
 
     el.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
-        schedule.execute(&mut world, &mut resources);
+        let now = Instant::now();
+        lag += now - last_frame;
+        last_frame = now;
+        let mut ticks = 0;
+        while lag >= TICK && ticks < MAX_TICKS_PER_FRAME {
+            lag -= TICK;
+            ticks += 1;
+            net_ticks += 1;
+            // spectators and clients only mirror the world simulated elsewhere
+            if let Some(one_spectator) = &mut spectator {
+                match one_spectator.receive() {
+                    Ok(true) => (),
+                    Ok(false) => *control_flow = ControlFlow::Exit,
+                    Err(err) => report_net_error(Err(err)),
+                }
+                if let Some(frame) = one_spectator.frame() {
+                    frame.apply(&mut world);
+                }
+                break;
+            }
+            if let Some(client) = &mut client {
+                match client.receive() {
+                    Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                        eprintln!("Failed to join match: {}", err);
+                        *control_flow = ControlFlow::Exit;
+                        break;
+                    }
+                    result => report_net_error(result),
+                }
+                if net_ticks % NET_SEND_INTERVAL == 0 {
+                    report_net_error(client.send_input(local_action));
+                }
+                client.interpolate(&mut world);
+                resources.get_mut::<components::Match>().unwrap().score = client.score();
+                continue;
+            }
+            if let Some(session) = &mut session {
+                match session.advance(&mut world, &mut resources, &mut schedule, local_action) {
+                    Ok(true) => {
+                        if let Some(broadcaster) = &mut broadcaster {
+                            report_net_error(broadcaster.broadcast(&world, &resources));
+                        }
+                        continue;
+                    }
+                    // wait for the remote peer to catch up
+                    Ok(false) => break,
+                    Err(err) => {
+                        report_net_error(Err(err));
+                        break;
+                    }
+                }
+            }
+            if let Some(host) = &mut host {
+                report_net_error(host.receive(&mut resources));
+            }
+            if playback.is_none() {
+                resources.get_mut::<components::Inputs>().unwrap().actions[0] = local_action;
+            }
+            if let Some(playback) = &mut playback {
+                if !replaying {
+                    break;
+                }
+                if !playback.apply(&mut resources) {
+                    replaying = false;
+                    if playback.verify(&world) {
+                        println!("Replay reproduced the recorded match");
+                    } else {
+                        println!("Replay diverged from the recorded match");
+                    }
+                    break;
+                }
+            }
+            if let Some(recording) = &mut recording {
+                recording.record(&resources);
+            }
+            schedule.execute(&mut world, &mut resources);
+            if let Some(host) = &mut host {
+                report_net_error(host.send_state(&world, &resources));
+            }
+            if let Some(broadcaster) = &mut broadcaster {
+                report_net_error(broadcaster.broadcast(&world, &resources));
+            }
+        }
+        if ticks == MAX_TICKS_PER_FRAME {
+            lag = Duration::default();
+        }
+        render.execute(&mut world, &mut resources);
         let mut vertex_data = Vec::<graphics::Vertex>::new();
         for one_info in resources
             .get::<Vec<components::RenderInfo>>()
@@ -103,12 +289,90 @@ fn main() {
             }
         }
         vertecies.store(&vertex_data);
+        let mut debug_vertex_data = Vec::<graphics::Vertex>::new();
+        for one_line in resources
+            .get::<Vec<components::DebugLine>>()
+            .unwrap()
+            .iter()
+        {
+            for one_vertex in &[one_line.from, one_line.to] {
+                debug_vertex_data.push(graphics::Vertex {
+                    color: one_line.color,
+                    vertex: (*one_vertex).into(),
+                });
+            }
+        }
+        debug_vertecies.store(&debug_vertex_data);
 
         match event {
-            Event::LoopDestroyed => return,
+            Event::LoopDestroyed => {
+                if let (Some(recording), Some(path)) = (&mut recording, &options.record) {
+                    recording.final_hash = snapshot::world_hash(&world);
+                    recording.save(path).expect("Failed to save replay");
+                }
+                return;
+            }
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::Resized(physical_size) => current_context.resize(physical_size),
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F1),
+                            ..
+                        },
+                    ..
+                } => {
+                    let mut debug = resources.get_mut::<components::DebugDraw>().unwrap();
+                    debug.enabled = !debug.enabled;
+                }
+                // pausing is not part of the recorded inputs
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::P),
+                            ..
+                        },
+                    ..
+                } if session.is_none() && recording.is_none() && playback.is_none() => {
+                    toggle_pause(&mut resources)
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F5),
+                            ..
+                        },
+                    ..
+                } => snapshot::Snapshot::capture(&world, &resources)
+                    .save(std::path::Path::new(QUICK_SAVE))
+                    .unwrap_or_else(|err| eprintln!("Failed to quick save: {}", err)),
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F9),
+                            ..
+                        },
+                    ..
+                } if recording.is_none() && playback.is_none() && !networked => {
+                    match snapshot::Snapshot::load(std::path::Path::new(QUICK_SAVE)) {
+                        Ok(quick_save) => quick_save.restore(&mut world, &mut resources),
+                        Err(err) => eprintln!("Failed to quick load: {}", err),
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => steer_paddle(&mut local_action, key, state),
                 _ => (),
             },
             _ => (),
@@ -119,6 +383,7 @@ fn main() {
             gl.ClearColor(0.0, 0.0, 1.0, 0.5);
             shader.bind();
             vertecies.draw(vertex_data.len() as i32);
+            debug_vertecies.draw(debug_vertex_data.len() as i32);
         }
         current_context.swap_buffers().unwrap();
     });
@@ -139,65 +404,36 @@ fn init_shader(gl: &gl::Gl) -> Program {
     shader
 }
 
-fn insert_components(world: &mut World) {
-    world.insert(
-        (components::Ball, ()),
-        vec![(
-            components::Transformation {
-                location: na::Vector2::new(300.0, 300.0),
-                rotation: 0.0,
-                scale: na::Vector2::new(1.0, 1.0),
-            },
-            components::RenderShape {
-                color: [1.0, 0.0, 0.0],
-                half_extents: na::Vector2::new(10.0, 10.0),
-            },
-            components::Velocity { velocity: 0.03 },
-            components::Hitbox {
-                shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
-                    10.0, 10.0,
-                ))),
-                slap_handle: None,
-            },
-        )],
-    );
-    world.insert(
-        (components::Barrier, ()),
-        vec![
-            (
-                components::Transformation {
-                    location: na::Vector2::new(600.0, 300.0),
-                    rotation: 0.0,
-                    scale: na::Vector2::new(1.0, 1.0),
-                },
-                components::RenderShape {
-                    color: [0.0, 1.0, 1.0],
-                    half_extents: na::Vector2::new(2.0, 40.0),
-                },
-                components::Hitbox {
-                    shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
-                        2.0, 40.0,
-                    ))),
-                    slap_handle: None,
-                },
-            ),
-            (
-                components::Transformation {
-                    location: na::Vector2::new(50.0, 300.0),
-                    rotation: 0.0,
-                    scale: na::Vector2::new(1.0, 1.0),
-                },
-                components::RenderShape {
-                    color: [0.0, 1.0, 1.0],
-                    half_extents: na::Vector2::new(2.0, 40.0),
-                },
-                components::Hitbox {
-                    shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
-                        2.0, 40.0,
-                    ))),
-                    slap_handle: None,
-                },
-            ),
-        ],
-    );
+fn report_net_error(result: std::io::Result<()>) {
+    if let Err(err) = result {
+        eprintln!("Network error: {}", err);
+    }
+}
+
+fn steer_paddle(local_action: &mut components::Action, key: VirtualKeyCode, state: ElementState) {
+    let action = match key {
+        VirtualKeyCode::W | VirtualKeyCode::Up => components::Action::Up,
+        VirtualKeyCode::S | VirtualKeyCode::Down => components::Action::Down,
+        _ => return,
+    };
+    match state {
+        ElementState::Pressed => *local_action = action,
+        ElementState::Released if *local_action == action => {
+            *local_action = components::Action::Stay;
+        }
+        ElementState::Released => (),
+    }
+}
+
+fn toggle_pause(resources: &mut Resources) {
+    let mut state = resources.get_mut::<components::GameState>().unwrap();
+    let mut game = resources.get_mut::<components::Match>().unwrap();
+    match game.paused.take() {
+        Some(previous) => *state = previous,
+        None if *state != components::GameState::GameOver => {
+            game.paused = Some(*state);
+            *state = components::GameState::Paused;
+        }
+        None => (),
+    }
 }
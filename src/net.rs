@@ -0,0 +1,480 @@
+use super::components;
+use super::replay;
+use legion::prelude::*;
+use nalgebra as na;
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+
+const MAGIC: &[u8; 2] = b"PN";
+/// Version 3 checks the scene of the client when it joins
+pub const PROTOCOL_VERSION: u8 = 3;
+/// Largest datagram the protocol sends
+const MAX_DATAGRAM: usize = 1200;
+
+const JOIN: u8 = 0;
+const ACCEPT: u8 = 1;
+const INPUT: u8 = 2;
+const STATE: u8 = 3;
+const INPUTS: u8 = 4;
+const REJECT: u8 = 5;
+
+/// Location and rotation of one entity in a `State` message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NetTransform {
+    pub location: [f32; 2],
+    pub rotation: f32,
+}
+
+/// Datagrams exchanged between host and client. Every datagram starts with
+/// `MAGIC`, the protocol version and the message type, all numbers are little
+/// endian.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// Sent by a client until the host accepts it, together with the
+    /// `world_hash` of the scene of the client
+    Join { scene_hash: u64 },
+    /// The host assigns the client the player it controls
+    Accept { player: u8 },
+    /// The host refuses a client whose scene differs from its own
+    Reject,
+    /// Action of the player of the client, `sequence` increases with every input
+    Input {
+        sequence: u32,
+        action: components::Action,
+    },
+    /// Transformations of all synced entities after the host tick `tick`
+    State {
+        tick: u32,
+        score: [u32; components::MAX_PLAYERS],
+        transforms: Vec<NetTransform>,
+    },
+    /// Actions of the sending peer for the ticks from `start` on, together with
+    /// the number of ticks of which the sender knows the actions of the receiver
+    Inputs {
+        ack: u32,
+        start: u32,
+        actions: Vec<components::Action>,
+    },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(PROTOCOL_VERSION);
+        match self {
+            Message::Join { scene_hash } => {
+                bytes.push(JOIN);
+                bytes.extend_from_slice(&scene_hash.to_le_bytes());
+            }
+            Message::Accept { player } => bytes.extend_from_slice(&[ACCEPT, *player]),
+            Message::Reject => bytes.push(REJECT),
+            Message::Input { sequence, action } => {
+                bytes.push(INPUT);
+                bytes.extend_from_slice(&sequence.to_le_bytes());
+                bytes.push(replay::encode_action(*action));
+            }
+            Message::State {
+                tick,
+                score,
+                transforms,
+            } => {
+                bytes.push(STATE);
+                bytes.extend_from_slice(&tick.to_le_bytes());
+                encode_score(&mut bytes, score);
+                bytes.extend_from_slice(&(transforms.len() as u16).to_le_bytes());
+                for one_transform in transforms {
+                    bytes.extend_from_slice(&one_transform.location[0].to_le_bytes());
+                    bytes.extend_from_slice(&one_transform.location[1].to_le_bytes());
+                    bytes.extend_from_slice(&one_transform.rotation.to_le_bytes());
+                }
+            }
+            Message::Inputs {
+                ack,
+                start,
+                actions,
+            } => {
+                bytes.push(INPUTS);
+                bytes.extend_from_slice(&ack.to_le_bytes());
+                bytes.extend_from_slice(&start.to_le_bytes());
+                bytes.extend_from_slice(&(actions.len() as u16).to_le_bytes());
+                bytes.extend(actions.iter().map(|action| replay::encode_action(*action)));
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> std::io::Result<Message> {
+        if bytes.len() < 4 || &bytes[0..2] != MAGIC || bytes[2] != PROTOCOL_VERSION {
+            return Err(replay::invalid_data(
+                "not a datagram of a supported version",
+            ));
+        }
+        let mut reader = &bytes[4..];
+        let message = match bytes[3] {
+            JOIN => Message::Join {
+                scene_hash: u64::from_le_bytes(read_array(&mut reader)?),
+            },
+            ACCEPT => Message::Accept {
+                player: read_array::<1>(&mut reader)?[0],
+            },
+            REJECT => Message::Reject,
+            INPUT => Message::Input {
+                sequence: u32::from_le_bytes(read_array(&mut reader)?),
+                action: replay::decode_action(read_array::<1>(&mut reader)?[0])?,
+            },
+            STATE => {
+                let tick = u32::from_le_bytes(read_array(&mut reader)?);
+                let score = decode_score(&mut reader)?;
+                let len = u16::from_le_bytes(read_array(&mut reader)?);
+                let mut transforms = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    transforms.push(NetTransform {
+                        location: [
+                            f32::from_le_bytes(read_array(&mut reader)?),
+                            f32::from_le_bytes(read_array(&mut reader)?),
+                        ],
+                        rotation: f32::from_le_bytes(read_array(&mut reader)?),
+                    });
+                }
+                Message::State {
+                    tick,
+                    score,
+                    transforms,
+                }
+            }
+            INPUTS => {
+                let ack = u32::from_le_bytes(read_array(&mut reader)?);
+                let start = u32::from_le_bytes(read_array(&mut reader)?);
+                let len = u16::from_le_bytes(read_array(&mut reader)?);
+                let mut actions = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    actions.push(replay::decode_action(read_array::<1>(&mut reader)?[0])?);
+                }
+                Message::Inputs {
+                    ack,
+                    start,
+                    actions,
+                }
+            }
+            _ => return Err(replay::invalid_data("unknown message type")),
+        };
+        if !reader.is_empty() {
+            return Err(replay::invalid_data("trailing bytes in datagram"));
+        }
+        Ok(message)
+    }
+}
+
+/// Entities whose `Transformation` is synced in order of their index. Host and
+/// client build the same scene, so their synced entities correspond by position.
+pub fn synced_entities(world: &World) -> Vec<Entity> {
+    let mut entities: Vec<Entity> = <Read<components::Transformation>>::query()
+        .iter_entities(world)
+        .map(|(entity, _)| entity)
+        .collect();
+    entities.sort_by_key(|entity| entity.index());
+    entities
+}
+
+/// Hands the paddle of `player` over from the AI to the `Inputs` of the player
+pub fn control_remotely(world: &mut World, player: usize, max_speed: f32) {
+    let paddles: Vec<Entity> = <Read<components::Player>>::query()
+        .filter(component::<components::AiController>())
+        .iter_entities(world)
+        .filter(|(_, one_player)| one_player.index == player)
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in paddles {
+        world
+            .remove_component::<components::AiController>(entity)
+            .unwrap();
+        world
+            .add_component(entity, components::InputController { max_speed })
+            .unwrap();
+    }
+}
+
+/// Authoritative end of a match, which simulates the world and accepts the
+/// inputs of one remote player
+pub struct Host {
+    socket: UdpSocket,
+    client: Option<SocketAddr>,
+    /// Player controlled by the client
+    player: usize,
+    /// `world_hash` of the scene, which has to match the one of the client
+    scene_hash: u64,
+    /// Ticks between two `State` messages
+    send_interval: u32,
+    tick: u32,
+    /// Sequence number of the latest applied input of the client
+    last_input: Option<u32>,
+}
+
+impl Host {
+    pub fn bind(
+        addr: SocketAddr,
+        player: usize,
+        scene_hash: u64,
+        send_interval: u32,
+    ) -> std::io::Result<Host> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Host {
+            socket,
+            client: None,
+            player,
+            scene_hash,
+            send_interval,
+            tick: 0,
+            last_input: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn client(&self) -> Option<SocketAddr> {
+        self.client
+    }
+
+    /// Handles all pending datagrams. Inputs of the client are written into `Inputs`.
+    pub fn receive(&mut self, resources: &mut Resources) -> std::io::Result<()> {
+        let mut buffer = [0; MAX_DATAGRAM];
+        while let Some((len, addr)) = receive_from(&self.socket, &mut buffer)? {
+            match Message::decode(&buffer[..len]) {
+                Ok(Message::Join { scene_hash }) if scene_hash != self.scene_hash => {
+                    self.socket.send_to(&Message::Reject.encode(), addr)?;
+                }
+                Ok(Message::Join { .. }) if self.client.is_none() || self.client == Some(addr) => {
+                    self.client = Some(addr);
+                    let accept = Message::Accept {
+                        player: self.player as u8,
+                    };
+                    self.socket.send_to(&accept.encode(), addr)?;
+                }
+                // datagrams may arrive out of order
+                Ok(Message::Input { sequence, action })
+                    if self.client == Some(addr)
+                        && self.last_input.is_none_or(|last| sequence > last) =>
+                {
+                    self.last_input = Some(sequence);
+                    resources.get_mut::<components::Inputs>().unwrap().actions[self.player] =
+                        action;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Has to be called after every tick of the schedule. Sends the state of
+    /// the world to the client every `send_interval` ticks.
+    pub fn send_state(&mut self, world: &World, resources: &Resources) -> std::io::Result<()> {
+        self.tick += 1;
+        let client = match self.client {
+            Some(client) if self.tick.is_multiple_of(self.send_interval) => client,
+            _ => return Ok(()),
+        };
+        let transforms = synced_entities(world)
+            .into_iter()
+            .map(|entity| {
+                let trans = world
+                    .get_component::<components::Transformation>(entity)
+                    .unwrap();
+                NetTransform {
+                    location: trans.location.into(),
+                    rotation: trans.rotation,
+                }
+            })
+            .collect();
+        let state = Message::State {
+            tick: self.tick,
+            score: resources.get::<components::Match>().unwrap().score,
+            transforms,
+        };
+        self.socket.send_to(&state.encode(), client)?;
+        Ok(())
+    }
+}
+
+/// Remote end of a match, which sends the inputs of its player and renders
+/// the state received from the host
+pub struct Client {
+    socket: UdpSocket,
+    host: SocketAddr,
+    scene_hash: u64,
+    player: Option<usize>,
+    sequence: u32,
+    /// Received states ordered by tick, oldest first
+    states: VecDeque<(u32, Vec<NetTransform>)>,
+    score: [u32; components::MAX_PLAYERS],
+    /// Host tick the client currently renders
+    clock: f32,
+    /// Ticks the rendered state lags behind the latest received one
+    delay: u32,
+}
+
+impl Client {
+    pub fn connect(
+        bind: SocketAddr,
+        host: SocketAddr,
+        scene_hash: u64,
+        delay: u32,
+    ) -> std::io::Result<Client> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_nonblocking(true)?;
+        let client = Client {
+            socket,
+            host,
+            scene_hash,
+            player: None,
+            sequence: 0,
+            states: VecDeque::new(),
+            score: [0; components::MAX_PLAYERS],
+            clock: 0.0,
+            delay,
+        };
+        client
+            .socket
+            .send_to(&Message::Join { scene_hash }.encode(), host)?;
+        Ok(client)
+    }
+
+    /// Player assigned by the host, `None` until the host accepted the client
+    pub fn player(&self) -> Option<usize> {
+        self.player
+    }
+
+    /// Score of the latest state received from the host
+    pub fn score(&self) -> [u32; components::MAX_PLAYERS] {
+        self.score
+    }
+
+    /// Sends the current action of the player. Until the host accepted the
+    /// client, the join request is repeated instead.
+    pub fn send_input(&mut self, action: components::Action) -> std::io::Result<()> {
+        let message = match self.player {
+            Some(_) => {
+                self.sequence += 1;
+                Message::Input {
+                    sequence: self.sequence,
+                    action,
+                }
+            }
+            None => Message::Join {
+                scene_hash: self.scene_hash,
+            },
+        };
+        self.socket.send_to(&message.encode(), self.host)?;
+        Ok(())
+    }
+
+    /// Handles all pending datagrams of the host. Fails with `InvalidData`
+    /// once the host rejected the scene of the client.
+    pub fn receive(&mut self) -> std::io::Result<()> {
+        let mut buffer = [0; MAX_DATAGRAM];
+        while let Some((len, addr)) = receive_from(&self.socket, &mut buffer)? {
+            if addr != self.host {
+                continue;
+            }
+            match Message::decode(&buffer[..len]) {
+                Ok(Message::Accept { player }) => self.player = Some(player as usize),
+                Ok(Message::Reject) => {
+                    return Err(replay::invalid_data("the host plays a different scene"));
+                }
+                // late datagrams are dropped
+                Ok(Message::State {
+                    tick,
+                    score,
+                    transforms,
+                }) if self.states.back().is_none_or(|latest| tick > latest.0) => {
+                    self.score = score;
+                    self.states.push_back((tick, transforms));
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Advances the clock by one tick and writes the state at the clock,
+    /// interpolated between the received states, into the `Transformation`s
+    /// of `world`.
+    pub fn interpolate(&mut self, world: &mut World) {
+        let latest = match self.states.back() {
+            Some(latest) => latest.0,
+            None => return,
+        };
+        let target = latest.saturating_sub(self.delay) as f32;
+        self.clock += 1.0;
+        // catch up with the host if the clock drifted too far
+        if (self.clock - target).abs() > self.delay as f32 {
+            self.clock = target;
+        }
+        while self.states.len() > 2 && (self.states[1].0 as f32) <= self.clock {
+            self.states.pop_front();
+        }
+        let (from, to) = match (self.states.front(), self.states.get(1)) {
+            (Some(from), Some(to)) => (from, to),
+            (Some(only), None) => (only, only),
+            _ => return,
+        };
+        let alpha = if to.0 > from.0 {
+            ((self.clock - from.0 as f32) / (to.0 - from.0) as f32).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let entities = synced_entities(world);
+        if entities.len() != to.1.len() || from.1.len() != to.1.len() {
+            return;
+        }
+        for (entity, (from, to)) in entities.into_iter().zip(from.1.iter().zip(to.1.iter())) {
+            let mut trans = world
+                .get_component_mut::<components::Transformation>(entity)
+                .unwrap();
+            let from_location = na::Vector2::from(from.location);
+            trans.location =
+                from_location + (na::Vector2::from(to.location) - from_location) * alpha;
+            trans.rotation = to.rotation;
+        }
+    }
+}
+
+/// Receives one datagram without blocking
+pub(crate) fn receive_from(
+    socket: &UdpSocket,
+    buffer: &mut [u8],
+) -> std::io::Result<Option<(usize, SocketAddr)>> {
+    match socket.recv_from(buffer) {
+        Ok(received) => Ok(Some(received)),
+        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+        // a previous datagram could not be delivered
+        Err(err) if err.kind() == std::io::ErrorKind::ConnectionReset => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+pub(crate) fn encode_score(bytes: &mut Vec<u8>, score: &[u32; components::MAX_PLAYERS]) {
+    for one_score in score {
+        bytes.extend_from_slice(&one_score.to_le_bytes());
+    }
+}
+
+pub(crate) fn decode_score(reader: &mut &[u8]) -> std::io::Result<[u32; components::MAX_PLAYERS]> {
+    let mut score = [0; components::MAX_PLAYERS];
+    for one_score in &mut score {
+        *one_score = u32::from_le_bytes(read_array(reader)?);
+    }
+    Ok(score)
+}
+
+pub(crate) fn read_array<const N: usize>(reader: &mut &[u8]) -> std::io::Result<[u8; N]> {
+    if reader.len() < N {
+        return Err(replay::invalid_data("datagram too short"));
+    }
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(&reader[..N]);
+    *reader = &reader[N..];
+    Ok(bytes)
+}
@@ -0,0 +1,148 @@
+/// Command line options shared by the binaries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    pub seed: Option<u64>,
+    /// Replay file whose inputs are played instead of live input
+    pub replay: Option<std::path::PathBuf>,
+    /// File the inputs of the match are recorded to
+    pub record: Option<std::path::PathBuf>,
+    /// Ticks after which a headless match stops
+    pub ticks: Option<u64>,
+    /// Address a hosted match accepts a remote player on
+    pub host: Option<std::net::SocketAddr>,
+    /// Address of the host of the match to join
+    pub connect: Option<std::net::SocketAddr>,
+    /// Local address of a peer to peer match
+    pub bind: Option<std::net::SocketAddr>,
+    /// Address of the other peer of a peer to peer match
+    pub peer: Option<std::net::SocketAddr>,
+    /// Player of the local peer of a peer to peer match, the other peer plays the other one
+    pub player: Option<usize>,
+    /// Address spectators of the match connect to
+    pub broadcast: Option<std::net::SocketAddr>,
+    /// Address of the broadcast to watch
+    pub spectate: Option<std::net::SocketAddr>,
+    /// Serves extra balls during a rally
+    pub multi_ball: bool,
+    /// Spawns power-ups into the arena
+    pub power_ups: bool,
+    /// Number of players, two if not given
+    pub players: Option<usize>,
+    /// Places obstacles into the arena
+    pub obstacles: bool,
+    /// Plays breakout instead of pong
+    pub breakout: bool,
+    /// Brick layout of a breakout match, which implies `breakout`
+    pub layout: Option<std::path::PathBuf>,
+    /// Number of balls of a stress scene, played instead of a match
+    pub stress: Option<usize>,
+    /// Cell size of a uniform grid replacing the DBVT broad phase
+    pub grid: Option<f32>,
+}
+
+impl Options {
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--seed" => options.seed = Some(parse(&value()?)?),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--record" => options.record = Some(value()?.into()),
+                "--ticks" => options.ticks = Some(parse(&value()?)?),
+                "--host" => options.host = Some(parse(&value()?)?),
+                "--connect" => options.connect = Some(parse(&value()?)?),
+                "--bind" => options.bind = Some(parse(&value()?)?),
+                "--peer" => options.peer = Some(parse(&value()?)?),
+                "--player" => options.player = Some(parse(&value()?)?),
+                "--broadcast" => options.broadcast = Some(parse(&value()?)?),
+                "--spectate" => options.spectate = Some(parse(&value()?)?),
+                "--multi-ball" => options.multi_ball = true,
+                "--power-ups" => options.power_ups = true,
+                "--players" => options.players = Some(parse(&value()?)?),
+                "--obstacles" => options.obstacles = true,
+                "--breakout" => options.breakout = true,
+                "--layout" => {
+                    options.layout = Some(value()?.into());
+                    options.breakout = true;
+                }
+                "--stress" => options.stress = Some(parse(&value()?)?),
+                "--grid" => options.grid = Some(parse(&value()?)?),
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        let modes = [
+            options.host,
+            options.connect,
+            options.peer,
+            options.spectate,
+        ];
+        if modes.iter().filter(|addr| addr.is_some()).count() > 1 {
+            return Err("--host, --connect, --peer and --spectate exclude each other".to_string());
+        }
+        if options.peer.is_some() != options.bind.is_some() {
+            return Err("--peer requires --bind and vice versa".to_string());
+        }
+        // both peers have to simulate the same match
+        if options.peer.is_some() != options.player.is_some() || options.player > Some(1) {
+            return Err("--peer requires --player 0 or 1 and vice versa".to_string());
+        }
+        if options.peer.is_some() && options.seed.is_none() {
+            return Err("--peer requires a --seed shared by both peers".to_string());
+        }
+        if let Some(players) = options.players {
+            if !(2..=super::components::MAX_PLAYERS).contains(&players) {
+                return Err(format!(
+                    "--players has to be between 2 and {}",
+                    super::components::MAX_PLAYERS
+                ));
+            }
+            // spectators watch the default scene of two players
+            let networked = options.host.or(options.connect).or(options.peer);
+            let watched = options.broadcast.or(options.spectate);
+            if players != 2 && (networked.is_some() || watched.is_some()) {
+                return Err("networked matches are played by two players".to_string());
+            }
+        }
+        if options.breakout {
+            let networked = options.host.or(options.connect).or(options.peer);
+            if options.players.is_some() || networked.is_some() {
+                return Err("breakout is played by a single local player".to_string());
+            }
+        }
+        if options.stress.is_some() {
+            let networked = options.host.or(options.connect).or(options.peer);
+            if options.breakout || options.players.is_some() || networked.is_some() {
+                return Err("a stress scene has no players".to_string());
+            }
+        }
+        // clients and spectators match the received state to their entities by
+        // order, which breaks once entities are spawned or removed
+        let streamed = options.host.or(options.connect);
+        let watched = options.broadcast.or(options.spectate);
+        if (streamed.is_some() || watched.is_some()) && (options.multi_ball || options.power_ups) {
+            return Err("clients and spectators can not follow spawned entities".to_string());
+        }
+        let custom_scene = options.breakout || options.obstacles || options.stress.is_some();
+        if watched.is_some() && custom_scene {
+            return Err("spectators can only watch a plain two player match".to_string());
+        }
+        let min_cell_size = super::scene::MIN_GRID_CELL_SIZE;
+        if !options
+            .grid
+            .is_none_or(|cell_size| cell_size >= min_cell_size)
+        {
+            return Err(format!("--grid has to be at least {}", min_cell_size));
+        }
+        Ok(options)
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {}", value))
+}
@@ -0,0 +1,40 @@
+use super::components;
+use nalgebra as na;
+
+/// Rasterizes the shapes of `infos` into an RGB image of `width` x `height`
+/// pixels showing the area from the origin to `view`. Every shape is drawn as
+/// its axis aligned bounding rectangle.
+pub fn rasterize(
+    infos: &[components::RenderInfo],
+    view: na::Vector2<f32>,
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let mut pixels = vec![0; width * height * 3];
+    let scale = na::Vector2::new(width as f32 / view[0], height as f32 / view[1]);
+    for one_info in infos {
+        let (min, max) = one_info.shape.iter().fold(
+            (
+                na::Vector2::repeat(f32::INFINITY),
+                na::Vector2::repeat(f32::NEG_INFINITY),
+            ),
+            |(min, max), vertex| (min.inf(vertex), max.sup(vertex)),
+        );
+        let min = min.component_mul(&scale);
+        let max = max.component_mul(&scale);
+        let x_range = (min[0].max(0.0) as usize)..(max[0].ceil().max(0.0) as usize).min(width);
+        let y_range = (min[1].max(0.0) as usize)..(max[1].ceil().max(0.0) as usize).min(height);
+        let color = [
+            (one_info.color[0] * 255.0) as u8,
+            (one_info.color[1] * 255.0) as u8,
+            (one_info.color[2] * 255.0) as u8,
+        ];
+        for y in y_range {
+            for x in x_range.clone() {
+                let idx = (y * width + x) * 3;
+                pixels[idx..idx + 3].copy_from_slice(&color);
+            }
+        }
+    }
+    pixels
+}
@@ -0,0 +1,245 @@
+use super::components;
+use super::options;
+use super::snapshot;
+use legion::prelude::*;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 4] = b"PONG";
+/// Version 2 stores the inputs of `MAX_PLAYERS` instead of two players,
+/// version 3 the `Settings` of the match
+const VERSION: u8 = 3;
+
+const MULTI_BALL: u8 = 1;
+const POWER_UPS: u8 = 1 << 1;
+const GRID: u8 = 1 << 2;
+
+/// Options which change the simulation, but not necessarily the scene, so
+/// the scene hash of a replay does not cover them
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub multi_ball: bool,
+    pub power_ups: bool,
+    pub players: Option<usize>,
+    pub grid: Option<f32>,
+}
+
+impl Settings {
+    pub fn of(options: &options::Options) -> Settings {
+        Settings {
+            multi_ball: options.multi_ball,
+            power_ups: options.power_ups,
+            players: options.players,
+            grid: options.grid,
+        }
+    }
+
+    /// Replaces the settings of `options` by these ones
+    pub fn apply(&self, options: &mut options::Options) {
+        options.multi_ball = self.multi_ball;
+        options.power_ups = self.power_ups;
+        options.players = self.players;
+        options.grid = self.grid;
+    }
+}
+
+/// Inputs of every tick of a match together with everything needed to
+/// simulate the match again. Consecutive equal inputs are stored as runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub settings: Settings,
+    /// `world_hash` of the scene before the first tick
+    pub scene_hash: u64,
+    /// `world_hash` after the last tick
+    pub final_hash: u64,
+    runs: Vec<([components::Action; components::MAX_PLAYERS], u32)>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, settings: Settings, scene_hash: u64) -> Replay {
+        Replay {
+            seed,
+            settings,
+            scene_hash,
+            final_hash: scene_hash,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.runs.iter().map(|run| run.1 as usize).sum()
+    }
+
+    /// Appends the current `Inputs` as the input of the next tick
+    pub fn record(&mut self, resources: &Resources) {
+        let actions = resources.get::<components::Inputs>().unwrap().actions;
+        match self.runs.last_mut() {
+            Some(run) if run.0 == actions && run.1 < u32::MAX => run.1 += 1,
+            _ => self.runs.push((actions, 1)),
+        }
+    }
+
+    /// Inputs of every recorded tick in order
+    pub fn inputs(
+        &self,
+    ) -> impl Iterator<Item = [components::Action; components::MAX_PLAYERS]> + '_ {
+        self.runs
+            .iter()
+            .flat_map(|(actions, len)| std::iter::repeat_n(*actions, *len as usize))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        let flags = [
+            (self.settings.multi_ball, MULTI_BALL),
+            (self.settings.power_ups, POWER_UPS),
+            (self.settings.grid.is_some(), GRID),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, flag)| flags | flag);
+        // no number of players is stored as 0
+        writer.write_all(&[flags, self.settings.players.unwrap_or(0) as u8])?;
+        writer.write_all(&self.settings.grid.unwrap_or(0.0).to_le_bytes())?;
+        writer.write_all(&self.scene_hash.to_le_bytes())?;
+        writer.write_all(&self.final_hash.to_le_bytes())?;
+        writer.write_all(&(self.runs.len() as u32).to_le_bytes())?;
+        for (actions, len) in &self.runs {
+            // two bits per player
+            let input = actions
+                .iter()
+                .enumerate()
+                .fold(0, |input, (player, action)| {
+                    input | encode_action(*action) << (2 * player)
+                });
+            writer.write_all(&[input])?;
+            writer.write_all(&len.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Replay> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if &magic != MAGIC || version[0] != VERSION {
+            return Err(invalid_data("not a replay of a supported version"));
+        }
+        let seed = read_u64(reader)?;
+        let mut settings = [0; 2];
+        reader.read_exact(&mut settings)?;
+        let mut grid = [0; 4];
+        reader.read_exact(&mut grid)?;
+        let [flags, players] = settings;
+        let settings = Settings {
+            multi_ball: flags & MULTI_BALL != 0,
+            power_ups: flags & POWER_UPS != 0,
+            players: Some(players as usize).filter(|players| *players > 0),
+            grid: Some(f32::from_le_bytes(grid)).filter(|_| flags & GRID != 0),
+        };
+        let scene_hash = read_u64(reader)?;
+        let final_hash = read_u64(reader)?;
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+        let mut runs = Vec::new();
+        for _ in 0..u32::from_le_bytes(len) {
+            let mut input = [0; 1];
+            reader.read_exact(&mut input)?;
+            let mut actions = [components::Action::Stay; components::MAX_PLAYERS];
+            for (player, action) in actions.iter_mut().enumerate() {
+                *action = decode_action(input[0] >> (2 * player))?;
+            }
+            let mut run_len = [0; 4];
+            reader.read_exact(&mut run_len)?;
+            runs.push((actions, u32::from_le_bytes(run_len)));
+        }
+        Ok(Replay {
+            seed,
+            settings,
+            scene_hash,
+            final_hash,
+            runs,
+        })
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    pub fn load(path: &std::path::Path) -> std::io::Result<Replay> {
+        Self::read(&mut std::io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+/// Feeds the inputs of a replay tick by tick into `Inputs`
+pub struct Playback {
+    replay: Replay,
+    run: usize,
+    offset: u32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            run: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Checks whether `world` ended in the same state as the recorded match
+    pub fn verify(&self, world: &World) -> bool {
+        snapshot::world_hash(world) == self.replay.final_hash
+    }
+
+    /// Writes the input of the next tick into `Inputs`. Returns false once
+    /// all recorded ticks were played.
+    pub fn apply(&mut self, resources: &mut Resources) -> bool {
+        while let Some((actions, len)) = self.replay.runs.get(self.run) {
+            if self.offset < *len {
+                self.offset += 1;
+                resources.get_mut::<components::Inputs>().unwrap().actions = *actions;
+                return true;
+            }
+            self.run += 1;
+            self.offset = 0;
+        }
+        false
+    }
+}
+
+pub(crate) fn encode_action(action: components::Action) -> u8 {
+    match action {
+        components::Action::Stay => 0,
+        components::Action::Up => 1,
+        components::Action::Down => 2,
+    }
+}
+
+pub(crate) fn decode_action(bits: u8) -> std::io::Result<components::Action> {
+    match bits & 0b11 {
+        0 => Ok(components::Action::Stay),
+        1 => Ok(components::Action::Up),
+        2 => Ok(components::Action::Down),
+        _ => Err(invalid_data("unknown action")),
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
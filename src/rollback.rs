@@ -0,0 +1,235 @@
+use super::components;
+use super::net;
+use super::snapshot;
+use legion::prelude::*;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Largest datagram a session sends or receives
+const MAX_DATAGRAM: usize = 1200;
+
+/// Unreliable datagram channel between two peers
+pub trait Transport {
+    fn send(&mut self, datagram: &[u8]) -> std::io::Result<()>;
+    /// Receives one datagram into `buffer` without blocking
+    fn receive(&mut self, buffer: &mut [u8]) -> std::io::Result<Option<usize>>;
+}
+
+/// A UDP socket connected to the other peer
+impl Transport for std::net::UdpSocket {
+    fn send(&mut self, datagram: &[u8]) -> std::io::Result<()> {
+        std::net::UdpSocket::send(self, datagram).map(|_| ())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> std::io::Result<Option<usize>> {
+        Ok(net::receive_from(self, buffer)?.map(|(len, _)| len))
+    }
+}
+
+/// A simulated tick together with the state before the tick
+struct Frame {
+    snapshot: snapshot::Snapshot,
+    /// Actions the tick was simulated with, the remote one may be predicted
+    actions: [components::Action; components::MAX_PLAYERS],
+}
+
+/// Peer to peer match, in which every peer simulates the whole world. Ticks
+/// are simulated right away with a prediction of the remote action. Once the
+/// actual remote action arrives and differs from the prediction, the world is
+/// rolled back to the mispredicted tick and simulated again.
+pub struct Session<T: Transport> {
+    transport: T,
+    local_player: usize,
+    /// Ticks the simulation runs ahead of the confirmed remote actions at most
+    max_rollback: u32,
+    /// Number of simulated ticks
+    tick: u32,
+    /// Frames of the ticks from `tick - frames.len()` on
+    frames: VecDeque<Frame>,
+    /// Confirmed remote actions by tick
+    remote: BTreeMap<u32, components::Action>,
+    /// Number of ticks for which the remote action is confirmed
+    remote_confirmed: u32,
+    /// Local actions not acknowledged by the remote peer yet
+    unacked: VecDeque<components::Action>,
+    /// Tick of the first action in `unacked`
+    unacked_start: u32,
+    rollbacks: u32,
+}
+
+impl<T: Transport> Session<T> {
+    pub fn new(transport: T, local_player: usize, max_rollback: u32) -> Session<T> {
+        Session {
+            transport,
+            local_player,
+            max_rollback,
+            tick: 0,
+            frames: VecDeque::new(),
+            remote: BTreeMap::new(),
+            remote_confirmed: 0,
+            unacked: VecDeque::new(),
+            unacked_start: 0,
+            rollbacks: 0,
+        }
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// Number of ticks simulated with the actual actions of both players
+    pub fn confirmed(&self) -> u32 {
+        self.remote_confirmed.min(self.tick)
+    }
+
+    /// Number of times the world was rolled back
+    pub fn rollbacks(&self) -> u32 {
+        self.rollbacks
+    }
+
+    /// Simulates the next tick, in which the local player performs `action`.
+    /// Returns false without simulating, if the remote peer is too far behind.
+    pub fn advance(
+        &mut self,
+        world: &mut World,
+        resources: &mut Resources,
+        schedule: &mut Schedule,
+        action: components::Action,
+    ) -> std::io::Result<bool> {
+        self.sync(world, resources, schedule)?;
+        if self.tick.saturating_sub(self.remote_confirmed) >= self.max_rollback {
+            return Ok(false);
+        }
+        let mut actions = [components::Action::Stay; components::MAX_PLAYERS];
+        actions[self.local_player] = action;
+        actions[1 - self.local_player] = self.remote_action(self.tick);
+        self.frames.push_back(Frame {
+            snapshot: snapshot::Snapshot::capture(world, resources),
+            actions,
+        });
+        if self.frames.len() > self.max_rollback as usize {
+            self.frames.pop_front();
+        }
+        simulate(world, resources, schedule, actions);
+        self.tick += 1;
+        self.unacked.push_back(action);
+        self.send()?;
+        Ok(true)
+    }
+
+    /// Receives the actions of the remote peer and simulates the ticks again,
+    /// whose remote action was mispredicted
+    pub fn sync(
+        &mut self,
+        world: &mut World,
+        resources: &mut Resources,
+        schedule: &mut Schedule,
+    ) -> std::io::Result<()> {
+        let mut buffer = [0; MAX_DATAGRAM];
+        let mut mispredicted = None;
+        while let Some(len) = self.transport.receive(&mut buffer)? {
+            if let Ok(net::Message::Inputs {
+                ack,
+                start,
+                actions,
+            }) = net::Message::decode(&buffer[..len])
+            {
+                self.acknowledge(ack);
+                if let Some(tick) = self.confirm(start, &actions) {
+                    mispredicted = Some(mispredicted.map_or(tick, |other: u32| other.min(tick)));
+                }
+            }
+        }
+        if let Some(tick) = mispredicted {
+            self.roll_back(world, resources, schedule, tick);
+        }
+        // keep the latest confirmed action as prediction
+        let first_frame = self.tick - self.frames.len() as u32;
+        self.remote = self
+            .remote
+            .split_off(&first_frame.min(self.remote_confirmed.saturating_sub(1)));
+        self.send()
+    }
+
+    fn send(&mut self) -> std::io::Result<()> {
+        let inputs = net::Message::Inputs {
+            ack: self.remote_confirmed,
+            start: self.unacked_start,
+            actions: self.unacked.iter().copied().collect(),
+        };
+        self.transport.send(&inputs.encode())
+    }
+
+    /// Drops the local actions the remote peer confirmed
+    fn acknowledge(&mut self, ack: u32) {
+        while self.unacked_start < ack && !self.unacked.is_empty() {
+            self.unacked.pop_front();
+            self.unacked_start += 1;
+        }
+    }
+
+    /// Stores the remote actions from tick `start` on. Returns the first
+    /// simulated tick whose remote action was mispredicted.
+    fn confirm(&mut self, start: u32, actions: &[components::Action]) -> Option<u32> {
+        let mut mispredicted = None;
+        let first_frame = self.tick - self.frames.len() as u32;
+        for (tick, action) in (start..).zip(actions) {
+            if tick != self.remote_confirmed {
+                continue;
+            }
+            self.remote.insert(tick, *action);
+            self.remote_confirmed += 1;
+            if tick >= first_frame && tick < self.tick {
+                let frame = &self.frames[(tick - first_frame) as usize];
+                if frame.actions[1 - self.local_player] != *action && mispredicted.is_none() {
+                    mispredicted = Some(tick);
+                }
+            }
+        }
+        mispredicted
+    }
+
+    /// Confirmed remote action of `tick` or the latest confirmed one as prediction
+    fn remote_action(&self, tick: u32) -> components::Action {
+        self.remote
+            .range(..=tick)
+            .next_back()
+            .map_or(components::Action::Stay, |(_, action)| *action)
+    }
+
+    fn roll_back(
+        &mut self,
+        world: &mut World,
+        resources: &mut Resources,
+        schedule: &mut Schedule,
+        tick: u32,
+    ) {
+        self.rollbacks += 1;
+        let first_frame = self.tick - self.frames.len() as u32;
+        let start = (tick - first_frame) as usize;
+        self.frames[start].snapshot.restore(world, resources);
+        for index in start..self.frames.len() {
+            let tick = first_frame + index as u32;
+            let remote_action = self.remote_action(tick);
+            let frame = &mut self.frames[index];
+            if index > start {
+                frame.snapshot = snapshot::Snapshot::capture(world, resources);
+            }
+            frame.actions[1 - self.local_player] = remote_action;
+            simulate(world, resources, schedule, frame.actions);
+        }
+    }
+}
+
+fn simulate(
+    world: &mut World,
+    resources: &mut Resources,
+    schedule: &mut Schedule,
+    actions: [components::Action; components::MAX_PLAYERS],
+) {
+    resources.get_mut::<components::Inputs>().unwrap().actions = actions;
+    schedule.execute(world, resources);
+}
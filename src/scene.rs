@@ -0,0 +1,566 @@
+use super::breakout;
+use super::collisions;
+use super::components;
+use super::events;
+use super::grid;
+use super::options;
+use super::systems;
+use legion::prelude::*;
+use nalgebra as na;
+
+/// Margin by which the broad phase loosens bounding volumes
+const BROAD_PHASE_MARGIN: f32 = 1.0;
+
+pub fn insert_resources(resources: &mut Resources, seed: u64) {
+    resources.insert(components::Rng::new(seed));
+    resources.insert(ncollide2d::pipeline::CollisionWorld::<f32, ()>::new(
+        BROAD_PHASE_MARGIN,
+    ));
+    resources.insert(std::collections::HashMap::<
+        ncollide2d::pipeline::CollisionObjectSlabHandle,
+        Entity,
+    >::new());
+    resources.insert(Vec::<[Entity; 2]>::new());
+    resources.insert(components::CollisionEvents::default());
+    resources.insert(events::Events::<components::GoalScored>::default());
+    resources.insert(events::Events::<components::PowerUpCollected>::default());
+    resources.insert(components::Arena {
+        min: na::Vector2::new(20.0, 4.0),
+        max: na::Vector2::new(630.0, 764.0),
+    });
+    resources.insert(components::Inputs {
+        actions: [components::Action::Stay; components::MAX_PLAYERS],
+    });
+    resources.insert(components::BounceConfig {
+        max_deflection: std::f32::consts::FRAC_PI_4,
+        momentum_transfer: 0.1,
+        spin_transfer: 0.003,
+        spin_decay: 0.999,
+    });
+    resources.insert(components::PaddlePhysics {
+        acceleration: 0.0005,
+        friction: 0.0005,
+    });
+    resources.insert(components::RallyConfig {
+        serve_velocity: 0.03,
+        speed_up: 1.1,
+        max_velocity: 0.12,
+    });
+    resources.insert(components::Rally { hits: 0 });
+    resources.insert(components::MultiBallConfig {
+        hits_per_ball: 0,
+        spawn_ticks: 0,
+        max_balls: 1,
+    });
+    resources.insert(components::MultiBall {
+        spawned: 0,
+        countdown: 0,
+        requested: 0,
+    });
+    resources.insert(components::PowerUpConfig {
+        spawn_ticks: 0,
+        max_power_ups: 2,
+        half_extents: na::Vector2::new(8.0, 8.0),
+        effect_ticks: 25000,
+        stick_ticks: 2500,
+    });
+    resources.insert(components::PowerUps { countdown: 0 });
+    resources.insert(components::GameState::Serve);
+    resources.insert(components::MatchConfig {
+        serve_ticks: 3000,
+        point_ticks: 3000,
+        points_to_win: 11,
+        win_by: 2,
+        serve_location: na::Vector2::new(325.0, 384.0),
+        serve_spread: 0.3,
+        lives: 0,
+    });
+    resources.insert(components::Match {
+        score: [0; components::MAX_PLAYERS],
+        conceded: [0; components::MAX_PLAYERS],
+        countdown: 3000,
+        receiver: 1,
+        paused: None,
+    });
+}
+
+/// Configuration of the multi-ball mode
+pub const MULTI_BALL: components::MultiBallConfig = components::MultiBallConfig {
+    hits_per_ball: 4,
+    spawn_ticks: 30000,
+    max_balls: 3,
+};
+
+/// Ticks between two power-ups when they are enabled
+pub const POWER_UP_TICKS: u32 = 25000;
+
+/// Goals a player concedes until it is eliminated in a match of more than two players
+pub const LIVES: u32 = 5;
+
+/// Systems simulating a match without any rendering
+pub fn build_simulation() -> legion::systems::schedule::Builder {
+    build_simulation_with(collisions::CollisionHandlers::default())
+}
+
+/// Systems simulating a match, in which `handlers` respond to collisions
+/// after the built-in collision responses
+pub fn build_simulation_with(
+    handlers: collisions::CollisionHandlers,
+) -> legion::systems::schedule::Builder {
+    Schedule::builder()
+        .add_system(systems::build_events_system::<components::CollisionEvent>())
+        .add_system(systems::build_events_system::<components::GoalScored>())
+        .add_system(systems::build_events_system::<components::PowerUpCollected>())
+        .add_system(systems::build_map_entity_collision_handle_system())
+        .add_thread_local_fn(systems::run_in_states(
+            &[components::GameState::Playing],
+            vec![
+                systems::build_ai_system(),
+                systems::build_input_system(),
+                systems::build_paddle_physics_system(),
+                systems::build_spin_system(),
+                systems::build_animation_system(),
+                systems::build_movement_system(),
+                systems::build_collision_system(),
+                systems::build_handle_ball_barrier_collision(),
+                systems::build_handle_ball_wall_collision(),
+                systems::build_handle_ball_ball_collision(),
+                systems::build_handle_ball_brick_collision(),
+                systems::build_handle_ball_obstacle_collision(),
+                systems::build_power_up_system(),
+                systems::build_effects_system(),
+                systems::build_rally_system(),
+                systems::build_multi_ball_system(),
+                systems::build_score_system(),
+            ],
+        ))
+        .add_thread_local_fn(handlers.into_step())
+        .add_system(systems::build_match_system())
+}
+
+/// Location of the paddle and the goal of a player and whether they are horizontal
+fn side(index: usize) -> (na::Vector2<f32>, na::Vector2<f32>, bool) {
+    match index {
+        0 => (
+            na::Vector2::new(50.0, 384.0),
+            na::Vector2::new(20.0, 384.0),
+            false,
+        ),
+        1 => (
+            na::Vector2::new(600.0, 384.0),
+            na::Vector2::new(630.0, 384.0),
+            false,
+        ),
+        2 => (
+            na::Vector2::new(325.0, 34.0),
+            na::Vector2::new(325.0, 2.0),
+            true,
+        ),
+        _ => (
+            na::Vector2::new(325.0, 734.0),
+            na::Vector2::new(325.0, 766.0),
+            true,
+        ),
+    }
+}
+
+pub(crate) fn ball() -> (
+    components::Transformation,
+    components::RenderShape,
+    components::Velocity,
+    components::Hitbox,
+    components::LastHit,
+    components::Effects,
+    components::Spin,
+    components::CollisionLayer,
+) {
+    (
+        components::Transformation {
+            location: na::Vector2::new(325.0, 384.0),
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+        },
+        components::RenderShape {
+            color: [1.0, 0.0, 0.0],
+            half_extents: na::Vector2::new(10.0, 10.0),
+        },
+        components::Velocity { velocity: 0.03 },
+        components::Hitbox {
+            shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(
+                10.0, 10.0,
+            ))),
+            slap_handle: None,
+        },
+        components::LastHit { barrier: None },
+        components::Effects::default(),
+        components::Spin::default(),
+        components::CollisionLayer::ball(),
+    )
+}
+
+pub(crate) fn paddle(
+    location: na::Vector2<f32>,
+    horizontal: bool,
+    index: usize,
+) -> (
+    components::Transformation,
+    components::RenderShape,
+    components::Hitbox,
+    components::Player,
+    components::PaddleMotion,
+    components::CollisionLayer,
+) {
+    let half_extents = if horizontal {
+        na::Vector2::new(40.0, 2.0)
+    } else {
+        na::Vector2::new(2.0, 40.0)
+    };
+    (
+        components::Transformation {
+            location,
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+        },
+        components::RenderShape {
+            color: [0.0, 1.0, 1.0],
+            half_extents,
+        },
+        components::Hitbox {
+            shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(half_extents)),
+            slap_handle: None,
+        },
+        components::Player { index },
+        components::PaddleMotion::default(),
+        components::CollisionLayer::paddle(),
+    )
+}
+
+pub(crate) fn wall(
+    location: na::Vector2<f32>,
+    half_extents: na::Vector2<f32>,
+) -> (
+    components::Transformation,
+    components::RenderShape,
+    components::Hitbox,
+    components::CollisionLayer,
+) {
+    (
+        components::Transformation {
+            location,
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+        },
+        components::RenderShape {
+            color: [1.0, 1.0, 1.0],
+            half_extents,
+        },
+        components::Hitbox {
+            shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(half_extents)),
+            slap_handle: None,
+        },
+        components::CollisionLayer::fixed(),
+    )
+}
+
+/// Goal of the player `index`, which spans a whole side of the arena
+pub(crate) fn goal(
+    index: usize,
+    location: na::Vector2<f32>,
+    horizontal: bool,
+) -> (
+    components::Transformation,
+    components::Player,
+    components::Hitbox,
+    components::CollisionLayer,
+) {
+    let half_extents = if horizontal {
+        na::Vector2::new(305.0, 2.0)
+    } else {
+        na::Vector2::new(2.0, 384.0)
+    };
+    (
+        components::Transformation {
+            location,
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+        },
+        components::Player { index },
+        components::Hitbox {
+            shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(half_extents)),
+            slap_handle: None,
+        },
+        components::CollisionLayer::fixed(),
+    )
+}
+
+/// Inserts a two player match
+pub fn insert_components(world: &mut World) {
+    insert_players(world, 2);
+}
+
+/// Inserts a match of two to `MAX_PLAYERS` players. The first two play on the
+/// left and right, the others on the top and bottom. Sides without a player
+/// are closed by walls. Player 0 is controlled by input, all others by the AI.
+pub fn insert_players(world: &mut World, players: usize) {
+    assert!((2..=components::MAX_PLAYERS).contains(&players));
+    world.insert((components::Ball, ()), vec![ball()]);
+    let (location, _, horizontal) = side(0);
+    let (trans, shape, hitbox, player, motion, layer) = paddle(location, horizontal, 0);
+    world.insert(
+        (components::Barrier, ()),
+        vec![(
+            trans,
+            shape,
+            hitbox,
+            player,
+            motion,
+            layer,
+            components::InputController { max_speed: 0.1 },
+            components::Effects::default(),
+        )],
+    );
+    world.insert(
+        (components::Barrier, ()),
+        (1..players).map(|index| {
+            let (location, _, horizontal) = side(index);
+            let (trans, shape, hitbox, player, motion, layer) = paddle(location, horizontal, index);
+            (
+                trans,
+                shape,
+                hitbox,
+                player,
+                motion,
+                layer,
+                components::AiController::with_difficulty(components::Difficulty::Medium),
+                components::Effects::default(),
+            )
+        }),
+    );
+    world.insert(
+        (components::Wall, ()),
+        (players..components::MAX_PLAYERS)
+            .map(|index| wall(side(index).1, na::Vector2::new(305.0, 2.0))),
+    );
+    world.insert(
+        (components::Goal, ()),
+        (0..players).map(|index| {
+            let (_, location, horizontal) = side(index);
+            goal(index, location, horizontal)
+        }),
+    );
+}
+
+pub(crate) fn obstacle(
+    kind: components::ObstacleKind,
+    location: na::Vector2<f32>,
+    shape: std::sync::Arc<dyn ncollide2d::shape::Shape<f32>>,
+    animation: components::Animation,
+) -> (
+    components::Transformation,
+    components::RenderShape,
+    components::Hitbox,
+    components::ObstacleKind,
+    components::Animation,
+    components::CollisionLayer,
+) {
+    let color = match kind {
+        components::ObstacleKind::Bar => [1.0, 1.0, 1.0],
+        components::ObstacleKind::Bumper { .. } => [1.0, 0.5, 0.0],
+        components::ObstacleKind::Portal { .. } => [0.5, 0.0, 1.0],
+    };
+    (
+        components::Transformation {
+            location,
+            rotation: 0.0,
+            scale: na::Vector2::new(1.0, 1.0),
+        },
+        components::RenderShape {
+            color,
+            half_extents: shape.local_aabb().half_extents(),
+        },
+        components::Hitbox {
+            shape,
+            slap_handle: None,
+        },
+        kind,
+        animation,
+        components::CollisionLayer::fixed(),
+    )
+}
+
+/// Inserts a rotating bar, two bumpers patrolling the halves of the arena and
+/// a pair of portals leading into each other
+pub fn insert_obstacles(world: &mut World) {
+    let patrol = |y: f32| components::Animation {
+        waypoints: vec![na::Vector2::new(250.0, y), na::Vector2::new(400.0, y)],
+        speed: 0.01,
+        next: 0,
+        angular_velocity: 0.0,
+    };
+    let bumper = || std::sync::Arc::new(ncollide2d::shape::Ball::new(15.0));
+    let portal = || std::sync::Arc::new(ncollide2d::shape::Ball::new(12.0));
+    let first_portal = na::Vector2::new(150.0, 120.0);
+    let second_portal = na::Vector2::new(500.0, 648.0);
+    world.insert(
+        (components::Obstacle, ()),
+        vec![
+            obstacle(
+                components::ObstacleKind::Bar,
+                na::Vector2::new(325.0, 130.0),
+                std::sync::Arc::new(ncollide2d::shape::Cuboid::new(na::Vector2::new(40.0, 3.0))),
+                components::Animation {
+                    angular_velocity: 0.0002,
+                    ..components::Animation::default()
+                },
+            ),
+            obstacle(
+                components::ObstacleKind::Bumper { boost: 1.2 },
+                na::Vector2::new(325.0, 250.0),
+                bumper(),
+                patrol(250.0),
+            ),
+            obstacle(
+                components::ObstacleKind::Bumper { boost: 1.2 },
+                na::Vector2::new(325.0, 518.0),
+                bumper(),
+                patrol(518.0),
+            ),
+            obstacle(
+                components::ObstacleKind::Portal {
+                    exit: second_portal,
+                },
+                first_portal,
+                portal(),
+                components::Animation::default(),
+            ),
+            obstacle(
+                components::ObstacleKind::Portal { exit: first_portal },
+                second_portal,
+                portal(),
+                components::Animation::default(),
+            ),
+        ],
+    );
+}
+
+/// Size of the balls of a stress scene
+const STRESS_HALF_EXTENTS: f32 = 2.0;
+
+/// Inserts `balls` small balls at random locations, heading into random
+/// directions at random velocities, into an arena closed by walls on every
+/// side. There are no players, so the balls bounce off the walls and each
+/// other forever.
+pub fn insert_stress(world: &mut World, resources: &mut Resources, balls: usize) {
+    let (min, max) = {
+        let arena = resources.get::<components::Arena>().unwrap();
+        (arena.min, arena.max)
+    };
+    // keep clear of the walls, which are centered on the bounds of the arena
+    let margin = na::Vector2::new(STRESS_HALF_EXTENTS + 4.0, STRESS_HALF_EXTENTS + 4.0);
+    let half_extents = na::Vector2::new(STRESS_HALF_EXTENTS, STRESS_HALF_EXTENTS);
+    let shape = std::sync::Arc::new(ncollide2d::shape::Cuboid::new(half_extents));
+    let center = (min + max) / 2.0;
+    let spread = (max - min) / 2.0 - margin;
+    let mut rng = resources.get_mut::<components::Rng>().unwrap();
+    let balls: Vec<_> = (0..balls)
+        .map(|_| {
+            let (mut trans, mut render, mut velocity, mut hitbox, last_hit, effects, spin, layer) =
+                ball();
+            trans.location =
+                center + na::Vector2::new(rng.signed() * spread[0], rng.signed() * spread[1]);
+            trans.rotation = rng.signed() * std::f32::consts::PI;
+            render.half_extents = half_extents;
+            velocity.velocity = 0.55 + rng.signed() * 0.45;
+            hitbox.shape = shape.clone();
+            (
+                trans, render, velocity, hitbox, last_hit, effects, spin, layer,
+            )
+        })
+        .collect();
+    drop(rng);
+    world.insert((components::Ball, ()), balls);
+    world.insert(
+        (components::Wall, ()),
+        (0..components::MAX_PLAYERS).map(|index| {
+            let (_, location, horizontal) = side(index);
+            let half_extents = if horizontal {
+                na::Vector2::new(305.0, 2.0)
+            } else {
+                na::Vector2::new(2.0, 384.0)
+            };
+            wall(location, half_extents)
+        }),
+    );
+    *resources.get_mut::<components::GameState>().unwrap() = components::GameState::Playing;
+}
+
+/// Smallest cell size of a uniform grid, smaller cells link the walls into
+/// so many cells that building the grid stalls
+pub const MIN_GRID_CELL_SIZE: f32 = 1.0;
+
+/// Replaces the DBVT broad phase of the collision world by a uniform grid of
+/// `cell_size`, before any hitbox was added to the world
+pub fn use_uniform_grid(resources: &mut Resources, cell_size: f32) {
+    let mut co_world = resources
+        .get_mut::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+        .unwrap();
+    assert_eq!(co_world.objects.len(), 0);
+    co_world.broad_phase = Box::new(grid::UniformGridBroadPhase::new(
+        cell_size,
+        BROAD_PHASE_MARGIN,
+    ));
+}
+
+/// Sets up the match described by `options` with `seed`, the same way for
+/// every binary. Returns the number of players.
+pub fn setup(
+    options: &options::Options,
+    seed: u64,
+    world: &mut World,
+    resources: &mut Resources,
+) -> std::io::Result<usize> {
+    insert_resources(resources, seed);
+    if options.multi_ball {
+        *resources.get_mut::<components::MultiBallConfig>().unwrap() = MULTI_BALL;
+    }
+    if options.power_ups {
+        resources
+            .get_mut::<components::PowerUpConfig>()
+            .unwrap()
+            .spawn_ticks = POWER_UP_TICKS;
+    }
+    if options.breakout {
+        breakout::insert_resources(resources);
+    }
+    if let Some(cell_size) = options.grid {
+        use_uniform_grid(resources, cell_size);
+    }
+    let players = if options.breakout {
+        1
+    } else if options.stress.is_some() {
+        0
+    } else {
+        options.players.unwrap_or(2)
+    };
+    if players > 2 {
+        resources
+            .get_mut::<components::MatchConfig>()
+            .unwrap()
+            .lives = LIVES;
+    }
+    if let Some(balls) = options.stress {
+        insert_stress(world, resources, balls);
+    } else if options.breakout {
+        let layout = match &options.layout {
+            Some(path) => breakout::Layout::load(path)?,
+            None => breakout::Layout::default(),
+        };
+        breakout::insert_components(world, &layout);
+    } else {
+        insert_players(world, players);
+    }
+    if options.obstacles {
+        insert_obstacles(world);
+    }
+    Ok(players)
+}
@@ -0,0 +1,294 @@
+use super::components;
+use super::events;
+use legion::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Components and tags of one entity in a `Snapshot`
+#[derive(Serialize, Deserialize)]
+struct EntitySnapshot {
+    transformation: Option<components::Transformation>,
+    velocity: Option<components::Velocity>,
+    render_shape: Option<components::RenderShape>,
+    hitbox: Option<components::Hitbox>,
+    collision_layer: Option<components::CollisionLayer>,
+    player: Option<components::Player>,
+    ai_controller: Option<components::AiController>,
+    input_controller: Option<components::InputController>,
+    paddle_motion: Option<components::PaddleMotion>,
+    spin: Option<components::Spin>,
+    last_hit: bool,
+    /// Position of the barrier of the `LastHit` in the snapshot
+    last_barrier: Option<usize>,
+    power_up_kind: Option<components::PowerUpKind>,
+    effects: Option<components::Effects>,
+    stuck: Option<components::Stuck>,
+    hit_points: Option<components::HitPoints>,
+    obstacle_kind: Option<components::ObstacleKind>,
+    animation: Option<components::Animation>,
+    ball: bool,
+    barrier: bool,
+    goal: bool,
+    wall: bool,
+    power_up: bool,
+    brick: bool,
+    obstacle: bool,
+}
+
+/// State of a match, which can be saved to disk and loaded back
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    entities: Vec<EntitySnapshot>,
+    rng: components::Rng,
+    state: components::GameState,
+    game: components::Match,
+    rally_hits: u32,
+    multi_ball: components::MultiBall,
+    power_ups: components::PowerUps,
+    /// Positions of the pairs of entities touching each other in the snapshot
+    collisions: Vec<[usize; 2]>,
+}
+
+impl Snapshot {
+    /// Captures all entities in order of their index together with the
+    /// resources describing the progress of the match
+    pub fn capture(world: &World, resources: &Resources) -> Snapshot {
+        let mut entities: Vec<Entity> = world.iter_entities().collect();
+        entities.sort_by_key(|entity| entity.index());
+        Snapshot {
+            entities: entities
+                .iter()
+                .map(|entity| capture_entity(world, *entity, &entities))
+                .collect(),
+            rng: resources.get::<components::Rng>().unwrap().clone(),
+            state: *resources.get::<components::GameState>().unwrap(),
+            game: *resources.get::<components::Match>().unwrap(),
+            rally_hits: resources.get::<components::Rally>().unwrap().hits,
+            multi_ball: *resources.get::<components::MultiBall>().unwrap(),
+            power_ups: *resources.get::<components::PowerUps>().unwrap(),
+            // pairs of entities removed during the last tick are left out
+            collisions: resources
+                .get::<Vec<[Entity; 2]>>()
+                .unwrap()
+                .iter()
+                .filter_map(|pair| {
+                    let position = |entity: Entity| entities.iter().position(|e| *e == entity);
+                    Some([position(pair[0])?, position(pair[1])?])
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces all entities of `world` with the ones of the snapshot. The
+    /// collision objects of the replaced entities are removed, the restored
+    /// hitboxes get registered by `map_entity_collision_handle`.
+    pub fn restore(&self, world: &mut World, resources: &mut Resources) {
+        {
+            let mut co_world = resources
+                .get_mut::<ncollide2d::pipeline::CollisionWorld<f32, ()>>()
+                .unwrap();
+            let handles: Vec<_> = <Read<components::Hitbox>>::query()
+                .iter(world)
+                .filter_map(|hitbox| hitbox.slap_handle)
+                .collect();
+            co_world.remove(&handles);
+        }
+        resources
+            .get_mut::<std::collections::HashMap<
+                ncollide2d::pipeline::CollisionObjectSlabHandle,
+                Entity,
+            >>()
+            .unwrap()
+            .clear();
+        // events of the replaced entities are dropped
+        resources
+            .get_mut::<components::CollisionEvents>()
+            .unwrap()
+            .clear();
+        resources
+            .get_mut::<events::Events<components::GoalScored>>()
+            .unwrap()
+            .clear();
+        resources
+            .get_mut::<events::Events<components::PowerUpCollected>>()
+            .unwrap()
+            .clear();
+        world.delete_all();
+
+        // entities are allocated upfront to keep their order of indices
+        let mut entities = world
+            .insert((), std::iter::repeat_n((), self.entities.len()))
+            .to_vec();
+        entities.sort_by_key(|entity| entity.index());
+        for (entity, one_entity) in entities.iter().zip(&self.entities) {
+            restore_entity(world, *entity, one_entity, &entities);
+        }
+        // pairs touching already do not start touching again once the
+        // collision world reports them
+        *resources.get_mut::<Vec<[Entity; 2]>>().unwrap() = self
+            .collisions
+            .iter()
+            .map(|pair| [entities[pair[0]], entities[pair[1]]])
+            .collect();
+        *resources.get_mut::<components::Rng>().unwrap() = self.rng.clone();
+        *resources.get_mut::<components::GameState>().unwrap() = self.state;
+        *resources.get_mut::<components::Match>().unwrap() = self.game;
+        *resources.get_mut::<components::Rally>().unwrap() = components::Rally {
+            hits: self.rally_hits,
+        };
+        *resources.get_mut::<components::MultiBall>().unwrap() = self.multi_ball;
+        *resources.get_mut::<components::PowerUps>().unwrap() = self.power_ups;
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(file, self).map_err(std::io::Error::from)
+    }
+
+    pub fn load(path: &std::path::Path) -> std::io::Result<Snapshot> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        serde_json::from_reader(file).map_err(std::io::Error::from)
+    }
+}
+
+fn capture_entity(world: &World, entity: Entity, entities: &[Entity]) -> EntitySnapshot {
+    let hitbox = world
+        .get_component::<components::Hitbox>(entity)
+        .map(|hitbox| components::Hitbox {
+            shape: hitbox.shape.clone(),
+            slap_handle: None,
+        });
+    let last_hit = world.get_component::<components::LastHit>(entity);
+    EntitySnapshot {
+        transformation: world.get_component(entity).map(|trans| *trans),
+        velocity: world.get_component(entity).map(|velocity| *velocity),
+        render_shape: world.get_component(entity).map(|shape| *shape),
+        hitbox,
+        collision_layer: world.get_component(entity).map(|layer| *layer),
+        player: world.get_component(entity).map(|player| *player),
+        ai_controller: world.get_component(entity).map(|ai| *ai),
+        input_controller: world.get_component(entity).map(|input| *input),
+        paddle_motion: world.get_component(entity).map(|motion| *motion),
+        spin: world.get_component(entity).map(|spin| *spin),
+        last_hit: last_hit.is_some(),
+        last_barrier: last_hit
+            .and_then(|last_hit| last_hit.barrier)
+            .and_then(|barrier| entities.iter().position(|entity| *entity == barrier)),
+        power_up_kind: world.get_component(entity).map(|kind| *kind),
+        effects: world
+            .get_component::<components::Effects>(entity)
+            .map(|effects| (*effects).clone()),
+        stuck: world.get_component(entity).map(|stuck| *stuck),
+        hit_points: world.get_component(entity).map(|hit_points| *hit_points),
+        obstacle_kind: world.get_component(entity).map(|kind| *kind),
+        animation: world
+            .get_component::<components::Animation>(entity)
+            .map(|animation| (*animation).clone()),
+        ball: world.get_tag::<components::Ball>(entity).is_some(),
+        barrier: world.get_tag::<components::Barrier>(entity).is_some(),
+        goal: world.get_tag::<components::Goal>(entity).is_some(),
+        wall: world.get_tag::<components::Wall>(entity).is_some(),
+        power_up: world.get_tag::<components::PowerUp>(entity).is_some(),
+        brick: world.get_tag::<components::Brick>(entity).is_some(),
+        obstacle: world.get_tag::<components::Obstacle>(entity).is_some(),
+    }
+}
+
+fn restore_entity(
+    world: &mut World,
+    entity: Entity,
+    one_entity: &EntitySnapshot,
+    entities: &[Entity],
+) {
+    add_component(world, entity, one_entity.transformation);
+    add_component(world, entity, one_entity.velocity);
+    add_component(world, entity, one_entity.render_shape);
+    add_component(
+        world,
+        entity,
+        one_entity.hitbox.as_ref().map(|hitbox| components::Hitbox {
+            shape: hitbox.shape.clone(),
+            slap_handle: None,
+        }),
+    );
+    add_component(world, entity, one_entity.collision_layer);
+    add_component(world, entity, one_entity.player);
+    add_component(world, entity, one_entity.ai_controller);
+    add_component(world, entity, one_entity.input_controller);
+    add_component(world, entity, one_entity.paddle_motion);
+    add_component(world, entity, one_entity.spin);
+    if one_entity.last_hit {
+        let barrier = one_entity.last_barrier.map(|position| entities[position]);
+        add_component(world, entity, Some(components::LastHit { barrier }));
+    }
+    add_component(world, entity, one_entity.power_up_kind);
+    add_component(world, entity, one_entity.effects.clone());
+    add_component(world, entity, one_entity.stuck);
+    add_component(world, entity, one_entity.hit_points);
+    add_component(world, entity, one_entity.obstacle_kind);
+    add_component(world, entity, one_entity.animation.clone());
+    add_tag(world, entity, one_entity.ball, components::Ball);
+    add_tag(world, entity, one_entity.barrier, components::Barrier);
+    add_tag(world, entity, one_entity.goal, components::Goal);
+    add_tag(world, entity, one_entity.wall, components::Wall);
+    add_tag(world, entity, one_entity.power_up, components::PowerUp);
+    add_tag(world, entity, one_entity.brick, components::Brick);
+    add_tag(world, entity, one_entity.obstacle, components::Obstacle);
+}
+
+fn add_component<T: legion::storage::Component>(
+    world: &mut World,
+    entity: Entity,
+    component: Option<T>,
+) {
+    if let Some(component) = component {
+        world.add_component(entity, component).unwrap();
+    }
+}
+
+fn add_tag<T: legion::storage::Tag>(world: &mut World, entity: Entity, present: bool, tag: T) {
+    if present {
+        world.add_tag(entity, tag).unwrap();
+    }
+}
+
+/// Hashes the `Transformation` and `Velocity` of all entities. Entities are
+/// visited in order of their index, so equal simulations result in equal
+/// hashes independent of the storage layout of their worlds.
+pub fn world_hash(world: &World) -> u64 {
+    let mut entities: Vec<Entity> = <Read<components::Transformation>>::query()
+        .iter_entities(world)
+        .map(|(entity, _)| entity)
+        .collect();
+    entities.sort_by_key(|entity| entity.index());
+    let mut hash = FNV_OFFSET;
+    for entity in entities {
+        let trans = world
+            .get_component::<components::Transformation>(entity)
+            .unwrap();
+        let mut values = vec![
+            trans.location[0],
+            trans.location[1],
+            trans.scale[0],
+            trans.scale[1],
+            trans.rotation,
+        ];
+        if let Some(velocity) = world.get_component::<components::Velocity>(entity) {
+            values.push(velocity.velocity);
+        }
+        for value in values {
+            hash = fnv1a(hash, &value.to_bits().to_le_bytes());
+        }
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
@@ -0,0 +1,335 @@
+use super::components;
+use super::net;
+use super::replay;
+use legion::prelude::*;
+use nalgebra as na;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+const MAGIC: &[u8; 2] = b"PS";
+pub const PROTOCOL_VERSION: u8 = 2;
+
+const FULL: u8 = 0;
+const DELTA: u8 = 1;
+
+/// Bytes queued for a spectator at most before it is dropped as too slow
+const MAX_BACKLOG: usize = 1 << 20;
+
+/// State of a match as seen by spectators
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tick: u32,
+    pub score: [u32; components::MAX_PLAYERS],
+    pub state: components::GameState,
+    /// Transformations of all synced entities
+    pub transforms: Vec<net::NetTransform>,
+}
+
+impl Frame {
+    pub fn capture(world: &World, resources: &Resources, tick: u32) -> Frame {
+        Frame {
+            tick,
+            score: resources.get::<components::Match>().unwrap().score,
+            state: *resources.get::<components::GameState>().unwrap(),
+            transforms: net::synced_entities(world)
+                .into_iter()
+                .map(|entity| {
+                    let trans = world
+                        .get_component::<components::Transformation>(entity)
+                        .unwrap();
+                    net::NetTransform {
+                        location: trans.location.into(),
+                        rotation: trans.rotation,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the transformations into the synced entities of `world`
+    pub fn apply(&self, world: &mut World) {
+        let entities = net::synced_entities(world);
+        if entities.len() != self.transforms.len() {
+            return;
+        }
+        for (entity, one_transform) in entities.into_iter().zip(&self.transforms) {
+            let mut trans = world
+                .get_component_mut::<components::Transformation>(entity)
+                .unwrap();
+            trans.location = na::Vector2::from(one_transform.location);
+            trans.rotation = one_transform.rotation;
+        }
+    }
+
+    /// Encodes the frame as a message, which only contains the transformations
+    /// that changed since `base`. Without a base all of them are included.
+    pub fn encode(&self, base: Option<&Frame>) -> Vec<u8> {
+        let base = base.filter(|base| base.transforms.len() == self.transforms.len());
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(PROTOCOL_VERSION);
+        bytes.push(if base.is_some() { DELTA } else { FULL });
+        bytes.extend_from_slice(&self.tick.to_le_bytes());
+        net::encode_score(&mut bytes, &self.score);
+        bytes.push(encode_state(self.state));
+        bytes.extend_from_slice(&(self.transforms.len() as u16).to_le_bytes());
+        let changed: Vec<bool> = match base {
+            Some(base) => self
+                .transforms
+                .iter()
+                .zip(&base.transforms)
+                .map(|(one_transform, base_transform)| one_transform != base_transform)
+                .collect(),
+            None => vec![true; self.transforms.len()],
+        };
+        if base.is_some() {
+            for chunk in changed.chunks(8) {
+                bytes.push(
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0, |mask, (bit, changed)| mask | ((*changed as u8) << bit)),
+                );
+            }
+        }
+        for (one_transform, _) in self.transforms.iter().zip(changed).filter(|one| one.1) {
+            bytes.extend_from_slice(&one_transform.location[0].to_le_bytes());
+            bytes.extend_from_slice(&one_transform.location[1].to_le_bytes());
+            bytes.extend_from_slice(&one_transform.rotation.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a message on top of `base`, which has to be the frame decoded
+    /// from the previous message
+    pub fn decode(bytes: &[u8], base: Option<&Frame>) -> std::io::Result<Frame> {
+        if bytes.len() < 4 || &bytes[0..2] != MAGIC || bytes[2] != PROTOCOL_VERSION {
+            return Err(replay::invalid_data("not a message of a supported version"));
+        }
+        let mut reader = &bytes[4..];
+        let tick = u32::from_le_bytes(net::read_array(&mut reader)?);
+        let score = net::decode_score(&mut reader)?;
+        let state = decode_state(net::read_array::<1>(&mut reader)?[0])?;
+        let len = u16::from_le_bytes(net::read_array(&mut reader)?) as usize;
+        let (mut transforms, changed) = match bytes[3] {
+            FULL => (Vec::with_capacity(len), vec![true; len]),
+            DELTA => {
+                let base = base
+                    .filter(|base| base.transforms.len() == len)
+                    .ok_or_else(|| replay::invalid_data("delta without matching base"))?;
+                let mut changed = Vec::with_capacity(len);
+                for _ in 0..len.div_ceil(8) {
+                    let mask = net::read_array::<1>(&mut reader)?[0];
+                    changed.extend((0..8).map(|bit| mask & (1 << bit) != 0));
+                }
+                changed.truncate(len);
+                (base.transforms.clone(), changed)
+            }
+            _ => return Err(replay::invalid_data("unknown message type")),
+        };
+        for (index, changed) in changed.into_iter().enumerate() {
+            if !changed {
+                continue;
+            }
+            let one_transform = net::NetTransform {
+                location: [
+                    f32::from_le_bytes(net::read_array(&mut reader)?),
+                    f32::from_le_bytes(net::read_array(&mut reader)?),
+                ],
+                rotation: f32::from_le_bytes(net::read_array(&mut reader)?),
+            };
+            match transforms.get_mut(index) {
+                Some(base_transform) => *base_transform = one_transform,
+                None => transforms.push(one_transform),
+            }
+        }
+        if !reader.is_empty() {
+            return Err(replay::invalid_data("trailing bytes in message"));
+        }
+        Ok(Frame {
+            tick,
+            score,
+            state,
+            transforms,
+        })
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// Encoded messages not written to the stream yet
+    backlog: Vec<u8>,
+}
+
+/// Streams the state of a match to any number of read-only spectators. Every
+/// message is prefixed with its length as `u32`.
+pub struct Broadcaster {
+    listener: TcpListener,
+    spectators: Vec<Connection>,
+    /// Ticks between two frames
+    interval: u32,
+    tick: u32,
+    last: Option<Frame>,
+}
+
+impl Broadcaster {
+    pub fn bind(addr: SocketAddr, interval: u32) -> std::io::Result<Broadcaster> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Broadcaster {
+            listener,
+            spectators: Vec::new(),
+            interval,
+            tick: 0,
+            last: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn spectators(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// Has to be called after every tick of the schedule. Every `interval`
+    /// ticks the changes since the previous frame are sent to the spectators,
+    /// while spectators who joined in the meantime get a full frame.
+    pub fn broadcast(&mut self, world: &World, resources: &Resources) -> std::io::Result<()> {
+        self.tick += 1;
+        if !self.tick.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+        let frame = Frame::capture(world, resources, self.tick);
+        let full = frame.encode(None);
+        let delta = frame.encode(self.last.as_ref());
+        for one_spectator in &mut self.spectators {
+            push_message(&mut one_spectator.backlog, &delta);
+        }
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    let mut backlog = Vec::new();
+                    push_message(&mut backlog, &full);
+                    self.spectators.push(Connection { stream, backlog });
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        // spectators, which disconnected or can not keep up, are dropped
+        self.spectators
+            .retain_mut(|one_spectator| flush(one_spectator).unwrap_or(false));
+        self.last = Some(frame);
+        Ok(())
+    }
+}
+
+fn push_message(backlog: &mut Vec<u8>, message: &[u8]) {
+    backlog.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    backlog.extend_from_slice(message);
+}
+
+/// Writes as much of the backlog as possible. Returns whether the spectator
+/// should be kept.
+fn flush(spectator: &mut Connection) -> std::io::Result<bool> {
+    while !spectator.backlog.is_empty() {
+        match spectator.stream.write(&spectator.backlog) {
+            Ok(0) => return Ok(false),
+            Ok(written) => {
+                spectator.backlog.drain(..written);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(spectator.backlog.len() <= MAX_BACKLOG)
+}
+
+/// Receives the frames of a `Broadcaster`
+pub struct Spectator {
+    stream: TcpStream,
+    /// Received bytes of incomplete messages
+    buffer: Vec<u8>,
+    frame: Option<Frame>,
+}
+
+impl Spectator {
+    pub fn connect(addr: SocketAddr) -> std::io::Result<Spectator> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        Ok(Spectator {
+            stream,
+            buffer: Vec::new(),
+            frame: None,
+        })
+    }
+
+    /// Latest received frame
+    pub fn frame(&self) -> Option<&Frame> {
+        self.frame.as_ref()
+    }
+
+    /// Handles all received messages. Returns false once the broadcaster
+    /// closed the stream. A message which can not be decoded closes the
+    /// stream, as the following deltas would be applied to a wrong base.
+    pub fn receive(&mut self) -> std::io::Result<bool> {
+        let mut open = true;
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    open = false;
+                    break;
+                }
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        while self.buffer.len() >= 4 {
+            let len = u32::from_le_bytes([
+                self.buffer[0],
+                self.buffer[1],
+                self.buffer[2],
+                self.buffer[3],
+            ]) as usize;
+            if self.buffer.len() < 4 + len {
+                break;
+            }
+            let message: Vec<u8> = self.buffer.drain(..4 + len).skip(4).collect();
+            match Frame::decode(&message, self.frame.as_ref()) {
+                Ok(frame) => self.frame = Some(frame),
+                Err(err) => {
+                    self.buffer.clear();
+                    self.stream.shutdown(std::net::Shutdown::Both)?;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(open)
+    }
+}
+
+fn encode_state(state: components::GameState) -> u8 {
+    match state {
+        components::GameState::Serve => 0,
+        components::GameState::Playing => 1,
+        components::GameState::PointScored => 2,
+        components::GameState::Paused => 3,
+        components::GameState::GameOver => 4,
+    }
+}
+
+fn decode_state(byte: u8) -> std::io::Result<components::GameState> {
+    match byte {
+        0 => Ok(components::GameState::Serve),
+        1 => Ok(components::GameState::Playing),
+        2 => Ok(components::GameState::PointScored),
+        3 => Ok(components::GameState::Paused),
+        4 => Ok(components::GameState::GameOver),
+        _ => Err(replay::invalid_data("unknown game state")),
+    }
+}
@@ -1,4 +1,6 @@
+use super::collisions::sort_collision_pair_by_tag;
 use super::components as comps;
+use super::events;
 use legion::filter;
 use legion::prelude::*;
 use nalgebra as na;
@@ -38,6 +40,14 @@ fn do_movement(
     }
 }
 
+/// Starts a new tick of the `Events<T>` resource, which drops the events of
+/// the tick before the previous one. It has to run before any writer.
+pub fn build_events_system<T: Send + Sync + 'static>() -> Box<dyn Schedulable> {
+    SystemBuilder::new(std::any::type_name::<events::Events<T>>())
+        .write_resource::<events::Events<T>>()
+        .build(|_, _, events, _| events.update())
+}
+
 pub fn build_map_entity_collision_handle_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("map_entity_collision_handle")
         .write_component::<comps::Hitbox>()
@@ -46,6 +56,7 @@ pub fn build_map_entity_collision_handle_system() -> Box<dyn Schedulable> {
             legion::entity::Entity,
         >>()
         .write_resource::<world::CollisionWorld<f32, ()>>()
+        .read_component::<comps::CollisionLayer>()
         .with_query(<Write<comps::Hitbox>>::query())
         .build(map_entity_collision_handle)
 }
@@ -73,21 +84,34 @@ pub fn map_entity_collision_handle(
         >,
     >,
 ) {
-    for (entity, mut hitbox) in query.iter_entities_mut(world) {
-        if hitbox.slap_handle.is_some() {
-            continue;
-        }
+    let unregistered: Vec<(Entity, std::sync::Arc<dyn ncollide2d::shape::Shape<f32>>)> = query
+        .iter_entities_mut(world)
+        .filter(|(_, hitbox)| hitbox.slap_handle.is_none())
+        .map(|(entity, hitbox)| (entity, hitbox.shape.clone()))
+        .collect();
+    for (entity, shape) in unregistered {
         let map = &mut resource.0;
-        let world = &mut resource.1;
-        let shape_handle = ncollide2d::shape::ShapeHandle::<f32>::from_arc(hitbox.shape.clone());
-        let (handle, co) = world.add(
+        let co_world = &mut resource.1;
+        // entities without a layer collide with everything
+        let (groups, query_type) = match world.get_component::<comps::CollisionLayer>(entity) {
+            Some(layer) => (layer.groups(), layer.query_type()),
+            None => (
+                ncollide2d::pipeline::CollisionGroups::new(),
+                ncollide2d::pipeline::GeometricQueryType::Proximity(1.0),
+            ),
+        };
+        let shape_handle = ncollide2d::shape::ShapeHandle::<f32>::from_arc(shape);
+        let (handle, _) = co_world.add(
             na::Isometry2::new(na::Vector2::new(0.0, 0.0), 0.0),
             shape_handle,
-            ncollide2d::pipeline::CollisionGroups::new(),
-            ncollide2d::pipeline::GeometricQueryType::Proximity(1.0),
+            groups,
+            query_type,
             (),
         );
-        hitbox.slap_handle = Some(handle);
+        world
+            .get_component_mut::<comps::Hitbox>(entity)
+            .unwrap()
+            .slap_handle = Some(handle);
         let old = map.insert(handle, entity);
         if old.is_some() {
             panic!("entries in the entity collision handle map should logically not be swapped");
@@ -97,7 +121,8 @@ pub fn map_entity_collision_handle(
 
 pub fn build_collision_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("collision")
-        .read_component::<comps::Transformation>()
+        .write_component::<comps::Transformation>()
+        .read_component::<comps::Velocity>()
         .write_component::<comps::Hitbox>()
         .read_resource::<std::collections::HashMap<
             ncollide2d::pipeline::CollisionObjectSlabHandle,
@@ -105,10 +130,13 @@ pub fn build_collision_system() -> Box<dyn Schedulable> {
         >>()
         .write_resource::<world::CollisionWorld<f32, ()>>()
         .write_resource::<std::vec::Vec<[Entity; 2]>>()
+        .write_resource::<comps::CollisionEvents>()
         .with_query(<(Read<comps::Transformation>, Write<comps::Hitbox>)>::query())
         .build(do_collision)
 }
 
+/// Updates the collision world, records all touching pairs and the pairs
+/// which started or stopped touching
 pub fn do_collision(
     _: &mut CommandBuffer,
     world: &mut SubWorld,
@@ -123,6 +151,7 @@ pub fn do_collision(
             ncollide2d::pipeline::world::CollisionWorld<f32, ()>,
         >,
         legion::systems::resource::PreparedWrite<std::vec::Vec<[Entity; 2]>>,
+        legion::systems::resource::PreparedWrite<comps::CollisionEvents>,
     ),
     query: &mut Query<
         (Read<comps::Transformation>, Write<comps::Hitbox>),
@@ -136,70 +165,1857 @@ pub fn do_collision(
         >,
     >,
 ) {
-    let (map, co_world, colliders) = resource;
+    let (map, co_world, colliders, events) = resource;
     // update shape position
-    for comp in query.iter_mut(world) {
+    let mut moved = Vec::new();
+    for (entity, comp) in query.iter_entities_mut(world) {
         let trans: legion::borrow::Ref<comps::Transformation> = comp.0;
         let mut hb: legion::borrow::RefMut<comps::Hitbox> = comp.1;
         if let Some(handle) = hb.slap_handle {
             if let Some(co) = co_world.objects.get_mut(handle) {
-                co.set_position(na::Isometry2::new(trans.location, trans.rotation));
+                // objects which did not move keep their place in the broad phase
+                let position = na::Isometry2::new(trans.location, trans.rotation);
+                if *co.position() != position {
+                    co.set_position(position);
+                    moved.push((entity, handle));
+                }
             }
         }
     }
+    for (entity, handle) in moved {
+        sweep(world, co_world, map, entity, handle);
+    }
     // do collision test
     co_world.update();
     // record collisions
-    colliders.clear();
-    for collision in co_world.proximity_pairs(true) {
-        let entities = [map[&collision.0], map[&collision.1]];
+    let previous = std::mem::take(&mut **colliders);
+    let proximities = co_world
+        .proximity_pairs(true)
+        .map(|(first, second, _, _)| (first, second));
+    let contacts = co_world
+        .contact_pairs(true)
+        .map(|(first, second, _, _)| (first, second));
+    for (first, second) in proximities.chain(contacts) {
+        let mut entities = [map[&first], map[&second]];
+        entities.sort_by_key(|entity| entity.index());
         colliders.push(entities);
     }
+    // do not depend on the order ncollide reports pairs in
+    colliders.sort_by_key(|pair| (pair[0].index(), pair[1].index()));
+
+    // only intersecting proximities count as touching, like in `proximity_pairs`
+    let proximity_events = co_world.proximity_events().iter().filter_map(|event| {
+        let was = event.prev_status == ncollide2d::query::Proximity::Intersecting;
+        let is = event.new_status == ncollide2d::query::Proximity::Intersecting;
+        (was != is).then_some((event.collider1, event.collider2, is))
+    });
+    let contact_events = co_world.contact_events().iter().map(|event| match *event {
+        ncollide2d::pipeline::ContactEvent::Started(first, second) => (first, second, true),
+        ncollide2d::pipeline::ContactEvent::Stopped(first, second) => (first, second, false),
+    });
+    let mut changes = Vec::new();
+    for (first, second, started) in proximity_events.chain(contact_events) {
+        let mut pair = [map[&first], map[&second]];
+        pair.sort_by_key(|entity| entity.index());
+        // a restored world reports pairs which were touching already as new ones
+        if started != previous.contains(&pair) {
+            changes.push(if started {
+                comps::CollisionEvent::Started(pair)
+            } else {
+                comps::CollisionEvent::Stopped(pair)
+            });
+        }
+    }
+    changes.sort_by_key(|event| match event {
+        comps::CollisionEvent::Started(pair) => (0, pair[0].index(), pair[1].index()),
+        comps::CollisionEvent::Stopped(pair) => (1, pair[0].index(), pair[1].index()),
+    });
+    for event in changes {
+        events.send(event);
+    }
 }
 
-fn sort_collision_pair_by_tag<T1, T2>(
+/// Moves an entity back to where it first touched a solid object during its
+/// last step, so it can not tunnel through thin objects. Steps shorter than
+/// the entity itself can not skip any object and are not swept.
+fn sweep(
     world: &mut SubWorld,
-    pair: &[Entity; 2],
-) -> Option<(Entity, Entity)>
-where
-    T1: Clone + PartialEq + Send + Sync + 'static,
-    T2: Clone + PartialEq + Send + Sync + 'static,
-{
-    let opt_first = pair
-        .iter()
-        .enumerate()
-        .find(|e| world.get_tag::<T1>(*e.1).is_some());
-    if let Some(first) = opt_first {
-        let second_idx = 1 - first.0;
-        if world.get_tag::<T2>(pair[second_idx]).is_some() {
-            return Some((*first.1, pair[second_idx]));
+    co_world: &mut world::CollisionWorld<f32, ()>,
+    map: &std::collections::HashMap<ncollide2d::pipeline::CollisionObjectSlabHandle, Entity>,
+    entity: Entity,
+    handle: ncollide2d::pipeline::CollisionObjectSlabHandle,
+) {
+    let velocity = match world.get_component::<comps::Velocity>(entity) {
+        Some(velocity) => velocity.velocity,
+        None => return,
+    };
+    let co = &co_world.objects[handle];
+    let half_extents = co.shape().local_aabb().half_extents();
+    if velocity < 2.0 * half_extents.min() {
+        return;
+    }
+    let end = *co.position();
+    let motion =
+        na::Vector2::new(end.rotation.angle().cos(), end.rotation.angle().sin()) * velocity;
+    let start = na::Isometry2::new(end.translation.vector - motion, end.rotation.angle());
+    let mut first = None;
+    for (other_handle, other) in co_world.collision_objects() {
+        let other_entity = match map.get(&other_handle) {
+            Some(other_entity) if other_handle != handle => *other_entity,
+            _ => continue,
+        };
+        // objects excluded by the collision groups are passed through
+        if !co
+            .collision_groups()
+            .can_interact_with_groups(other.collision_groups())
+        {
+            continue;
+        }
+        // balls and collectibles do not stop other balls
+        if world.get_tag::<comps::Ball>(other_entity).is_some()
+            || world.get_tag::<comps::PowerUp>(other_entity).is_some()
+        {
+            continue;
+        }
+        let toi = ncollide2d::query::time_of_impact(
+            &start,
+            &motion,
+            co.shape().as_ref(),
+            other.position(),
+            &na::Vector2::zeros(),
+            other.shape().as_ref(),
+            1.0,
+            0.0,
+        );
+        if let Some(toi) = toi {
+            if toi.status != ncollide2d::query::TOIStatus::Penetrating
+                && first.is_none_or(|first| toi.toi < first)
+            {
+                first = Some(toi.toi);
+            }
+        }
+    }
+    if let Some(toi) = first {
+        // reach slightly into the object, so the proximity test reports the contact
+        let toi = (toi + SWEEP_PENETRATION / velocity).min(1.0);
+        let location = start.translation.vector + motion * toi;
+        co_world.objects[handle].set_position(na::Isometry2::new(location, end.rotation.angle()));
+        if let Some(mut trans) = world.get_component_mut::<comps::Transformation>(entity) {
+            trans.location = location;
         }
     }
-    None
 }
 
+/// Distance a swept entity is moved into the object it hits
+const SWEEP_PENETRATION: f32 = 0.5;
+
 pub fn build_handle_ball_barrier_collision() -> Box<dyn Schedulable> {
     SystemBuilder::new("handle_ball_barrier_collision")
-        .read_resource::<std::vec::Vec<[Entity; 2]>>()
+        .read_resource::<comps::CollisionEvents>()
+        .read_resource::<comps::BounceConfig>()
         .write_component::<comps::Transformation>()
+        .read_component::<comps::Hitbox>()
+        .read_component::<comps::PaddleMotion>()
+        .write_component::<comps::Velocity>()
+        .write_component::<comps::Spin>()
         .build(handle_ball_barrier_collision)
 }
 
 fn handle_ball_barrier_collision(
     _: &mut CommandBuffer,
     world: &mut SubWorld,
-    colliders: &mut legion::systems::resource::PreparedRead<std::vec::Vec<[Entity; 2]>>,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedRead<comps::BounceConfig>,
+    ),
     _: &mut (),
 ) {
-    for one_collision in colliders.iter() {
-        if let Some((ball, _)) =
+    let (collisions, config) = resource;
+    for one_collision in collisions.started() {
+        if let Some((ball, barrier)) =
             sort_collision_pair_by_tag::<comps::Ball, comps::Barrier>(world, one_collision)
         {
-            world
+            let half_extents = world
+                .get_component::<comps::Hitbox>(barrier)
+                .map(|hitbox| hitbox.shape.local_aabb().half_extents());
+            let barrier_location = world
+                .get_component::<comps::Transformation>(barrier)
+                .unwrap()
+                .location;
+            let mut trans = world
+                .get_component_mut::<comps::Transformation>(ball)
+                .unwrap();
+            let half_extents = match half_extents {
+                Some(half_extents) => half_extents,
+                None => {
+                    trans.rotation += std::f32::consts::PI;
+                    continue;
+                }
+            };
+            // a paddle catching up with a ball from behind does not bounce it back
+            let across = 1 - long_axis(half_extents);
+            let incoming = na::Vector2::new(trans.rotation.cos(), trans.rotation.sin());
+            let side = trans.location[across] - barrier_location[across];
+            if side * incoming[across] > 0.0 {
+                continue;
+            }
+            trans.rotation = bounce_rotation(
+                &trans,
+                barrier_location,
+                half_extents,
+                config.max_deflection,
+            );
+            drop(trans);
+
+            // the ball picks up part of the motion of the paddle and starts to spin
+            let paddle_velocity = match world.get_component::<comps::PaddleMotion>(barrier) {
+                Some(motion) => {
+                    let mut paddle_velocity = na::Vector2::zeros();
+                    paddle_velocity[1 - across] = motion.velocity;
+                    paddle_velocity
+                }
+                None => continue,
+            };
+            let rotation = world
+                .get_component::<comps::Transformation>(ball)
+                .unwrap()
+                .rotation;
+            let outgoing = na::Vector2::new(rotation.cos(), rotation.sin());
+            let combined = world
+                .get_component_mut::<comps::Velocity>(ball)
+                .map(|mut velocity| {
+                    let combined =
+                        outgoing * velocity.velocity + paddle_velocity * config.momentum_transfer;
+                    velocity.velocity = combined.norm();
+                    combined
+                });
+            if let Some(combined) = combined.filter(|combined| combined.norm() > 0.0) {
+                world
+                    .get_component_mut::<comps::Transformation>(ball)
+                    .unwrap()
+                    .rotation = combined[1].atan2(combined[0]);
+            }
+            if let Some(mut spin) = world.get_component_mut::<comps::Spin>(ball) {
+                let cross = outgoing[0] * paddle_velocity[1] - outgoing[1] * paddle_velocity[0];
+                spin.angular_velocity = config.spin_transfer * cross;
+            }
+        }
+    }
+}
+
+/// Computes the outgoing rotation of a ball hitting a barrier. The ball leaves
+/// along the barrier normal facing it, deflected towards the barrier's long axis
+/// proportional to the distance of the hit from the barrier center.
+fn bounce_rotation(
+    ball: &comps::Transformation,
+    barrier_location: na::Vector2<f32>,
+    half_extents: na::Vector2<f32>,
+    max_deflection: f32,
+) -> f32 {
+    let (along, normal, half_length) = if half_extents[1] >= half_extents[0] {
+        (na::Vector2::y(), na::Vector2::x(), half_extents[1])
+    } else {
+        (na::Vector2::x(), na::Vector2::y(), half_extents[0])
+    };
+    let offset = ball.location - barrier_location;
+    let mut side = offset.dot(&normal);
+    if side == 0.0 {
+        let incoming = na::Vector2::new(ball.rotation.cos(), ball.rotation.sin());
+        side = -incoming.dot(&normal);
+    }
+    let normal = if side < 0.0 { -normal } else { normal };
+    let relative = (offset.dot(&along) / half_length).clamp(-1.0, 1.0);
+    let deflection = relative * max_deflection;
+    let outgoing = normal * deflection.cos() + along * deflection.sin();
+    outgoing[1].atan2(outgoing[0])
+}
+
+pub fn build_handle_ball_wall_collision() -> Box<dyn Schedulable> {
+    SystemBuilder::new("handle_ball_wall_collision")
+        .read_resource::<comps::CollisionEvents>()
+        .write_component::<comps::Transformation>()
+        .read_component::<comps::Hitbox>()
+        .build(handle_ball_wall_collision)
+}
+
+fn handle_ball_wall_collision(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    collisions: &mut legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+    _: &mut (),
+) {
+    for one_collision in collisions.started() {
+        if let Some((ball, wall)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Wall>(world, one_collision)
+        {
+            // walls are reflecting along their short axis
+            let axis = world
+                .get_component::<comps::Hitbox>(wall)
+                .map_or(1, |hitbox| {
+                    1 - long_axis(hitbox.shape.local_aabb().half_extents())
+                });
+            let wall_location = world
+                .get_component::<comps::Transformation>(wall)
+                .unwrap()
+                .location[axis];
+            let mut trans = world
                 .get_component_mut::<comps::Transformation>(ball)
+                .unwrap();
+            // always head away from the wall, whichever way the ball touched it
+            let mut direction = na::Vector2::new(trans.rotation.cos(), trans.rotation.sin());
+            direction[axis] = if trans.location[axis] < wall_location {
+                -direction[axis].abs()
+            } else {
+                direction[axis].abs()
+            };
+            trans.rotation = direction[1].atan2(direction[0]);
+        }
+    }
+}
+
+pub fn build_handle_ball_brick_collision() -> Box<dyn Schedulable> {
+    SystemBuilder::new("handle_ball_brick_collision")
+        .read_resource::<comps::CollisionEvents>()
+        .write_resource::<comps::Match>()
+        .write_resource::<comps::GameState>()
+        .write_resource::<world::CollisionWorld<f32, ()>>()
+        .write_resource::<std::collections::HashMap<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            legion::entity::Entity,
+        >>()
+        .write_component::<comps::Transformation>()
+        .write_component::<comps::HitPoints>()
+        .write_component::<comps::RenderShape>()
+        .read_component::<comps::Hitbox>()
+        .read_component::<comps::LastHit>()
+        .read_component::<comps::Player>()
+        .with_query(<Read<comps::HitPoints>>::query().filter(tag::<comps::Brick>()))
+        .build(handle_ball_brick_collision)
+}
+
+/// Reflects balls off bricks along the axis they penetrate the least and
+/// damages the bricks. Only a ball heading into a brick when it starts
+/// touching it hits it. A destroyed brick is awarded to the player who hit
+/// the ball last, the match is over once no brick is left.
+fn handle_ball_brick_collision(
+    commands: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedWrite<comps::Match>,
+        legion::systems::resource::PreparedWrite<comps::GameState>,
+        legion::systems::resource::PreparedWrite<
+            ncollide2d::pipeline::world::CollisionWorld<f32, ()>,
+        >,
+        legion::systems::resource::PreparedWrite<
+            std::collections::HashMap<
+                ncollide2d::pipeline::CollisionObjectSlabHandle,
+                legion::entity::Entity,
+            >,
+        >,
+    ),
+    bricks_query: &mut Query<
+        Read<comps::HitPoints>,
+        filter::EntityFilterTuple<
+            filter::And<(
+                filter::ComponentFilter<comps::HitPoints>,
+                filter::TagFilter<comps::Brick>,
+            )>,
+            filter::Passthrough,
+            filter::Passthrough,
+        >,
+    >,
+) {
+    let (collisions, game, state, co_world, map) = resource;
+    let mut destroyed = Vec::new();
+    for one_collision in collisions.started() {
+        if let Some((ball, brick)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Brick>(world, one_collision)
+        {
+            if destroyed.contains(&brick) {
+                continue;
+            }
+            let half_extents = |world: &SubWorld, entity: Entity| {
+                world
+                    .get_component::<comps::Hitbox>(entity)
+                    .map_or(na::Vector2::zeros(), |hitbox| {
+                        hitbox.shape.local_aabb().half_extents()
+                    })
+            };
+            let extents = half_extents(world, brick) + half_extents(world, ball);
+            let brick_location = world
+                .get_component::<comps::Transformation>(brick)
                 .unwrap()
-                .rotation += std::f32::consts::PI;
+                .location;
+            {
+                let mut trans = world
+                    .get_component_mut::<comps::Transformation>(ball)
+                    .unwrap();
+                let offset = trans.location - brick_location;
+                let axis = entry_axis(offset, extents);
+                let mut direction = na::Vector2::new(trans.rotation.cos(), trans.rotation.sin());
+                if direction[axis] * offset[axis] >= 0.0 {
+                    continue;
+                }
+                direction[axis] = -direction[axis];
+                trans.rotation = direction[1].atan2(direction[0]);
+            }
+            let hit_points = {
+                let mut hit_points = world.get_component_mut::<comps::HitPoints>(brick).unwrap();
+                hit_points.remaining = hit_points.remaining.saturating_sub(1);
+                *hit_points
+            };
+            if hit_points.remaining > 0 {
+                if let Some(mut shape) = world.get_component_mut::<comps::RenderShape>(brick) {
+                    shape.color = hit_points.color();
+                }
+                continue;
+            }
+            let hitter = world
+                .get_component::<comps::LastHit>(ball)
+                .and_then(|last_hit| last_hit.barrier)
+                .and_then(|barrier| world.get_component::<comps::Player>(barrier))
+                .map(|player| player.index);
+            if let Some(hitter) = hitter {
+                game.score[hitter] += 1;
+            }
+            remove_from_play(commands, world, co_world, map, brick);
+            destroyed.push(brick);
+        }
+    }
+    if !destroyed.is_empty() && bricks_query.iter(world).count() == destroyed.len() {
+        **state = comps::GameState::GameOver;
+    }
+}
+
+/// Axis of the side a box entered another box through, which is the one it
+/// penetrates the least relative to their combined `extents`
+fn entry_axis(offset: na::Vector2<f32>, extents: na::Vector2<f32>) -> usize {
+    if offset[0].abs() * extents[1] > offset[1].abs() * extents[0] {
+        0
+    } else {
+        1
+    }
+}
+
+pub fn build_animation_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("animation")
+        .with_query(<(Write<comps::Animation>, Write<comps::Transformation>)>::query())
+        .build(animate)
+}
+
+/// Rotates animated entities and moves them towards their next waypoint
+fn animate(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    _: &mut (),
+    query: &mut Query<
+        (Write<comps::Animation>, Write<comps::Transformation>),
+        filter::EntityFilterTuple<
+            filter::And<(
+                filter::ComponentFilter<comps::Animation>,
+                filter::ComponentFilter<comps::Transformation>,
+            )>,
+            filter::And<(filter::Passthrough, filter::Passthrough)>,
+            filter::And<(filter::Passthrough, filter::Passthrough)>,
+        >,
+    >,
+) {
+    for (mut animation, mut trans) in query.iter_mut(world) {
+        trans.rotation = (trans.rotation + animation.angular_velocity) % std::f32::consts::TAU;
+        if animation.waypoints.is_empty() {
+            continue;
+        }
+        let target = animation.waypoints[animation.next % animation.waypoints.len()];
+        let remaining = target - trans.location;
+        if remaining.norm() <= animation.speed {
+            trans.location = target;
+            animation.next = (animation.next + 1) % animation.waypoints.len();
+        } else {
+            trans.location += remaining.normalize() * animation.speed;
+        }
+    }
+}
+
+pub fn build_handle_ball_obstacle_collision() -> Box<dyn Schedulable> {
+    SystemBuilder::new("handle_ball_obstacle_collision")
+        .read_resource::<comps::CollisionEvents>()
+        .read_resource::<comps::RallyConfig>()
+        .write_component::<comps::Transformation>()
+        .write_component::<comps::Velocity>()
+        .read_component::<comps::Hitbox>()
+        .read_component::<comps::ObstacleKind>()
+        .build(handle_ball_obstacle_collision)
+}
+
+/// Responds to balls touching an obstacle according to its `ObstacleKind`.
+/// Like the other collision responses only a ball heading into an obstacle
+/// is affected, so a ball leaving a portal is not sent back.
+fn handle_ball_obstacle_collision(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedRead<comps::RallyConfig>,
+    ),
+    _: &mut (),
+) {
+    let (collisions, config) = resource;
+    for one_collision in collisions.started() {
+        if let Some((ball, obstacle)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Obstacle>(world, one_collision)
+        {
+            let kind = match world.get_component::<comps::ObstacleKind>(obstacle) {
+                Some(kind) => *kind,
+                None => continue,
+            };
+            let half_extents = |world: &SubWorld, entity: Entity| {
+                world
+                    .get_component::<comps::Hitbox>(entity)
+                    .map_or(na::Vector2::zeros(), |hitbox| {
+                        hitbox.shape.local_aabb().half_extents()
+                    })
+            };
+            let obstacle_trans = *world
+                .get_component::<comps::Transformation>(obstacle)
+                .unwrap();
+            let extents = half_extents(world, obstacle) + half_extents(world, ball);
+            let mut trans = world
+                .get_component_mut::<comps::Transformation>(ball)
+                .unwrap();
+            let direction = na::Vector2::new(trans.rotation.cos(), trans.rotation.sin());
+            let offset = trans.location - obstacle_trans.location;
+            match kind {
+                comps::ObstacleKind::Bar => {
+                    // reflect at the side the ball entered the rotated bar through
+                    let rotation = na::Rotation2::new(obstacle_trans.rotation);
+                    let local = rotation.inverse() * offset;
+                    let axis = entry_axis(local, extents);
+                    let mut normal = na::Vector2::zeros();
+                    normal[axis] = local[axis].signum();
+                    let normal = rotation * normal;
+                    let approach = direction.dot(&normal);
+                    if approach < 0.0 {
+                        let outgoing = direction - normal * (2.0 * approach);
+                        trans.rotation = outgoing[1].atan2(outgoing[0]);
+                    }
+                }
+                comps::ObstacleKind::Bumper { boost } => {
+                    if offset.norm() == 0.0 {
+                        continue;
+                    }
+                    let normal = offset.normalize();
+                    let approach = direction.dot(&normal);
+                    if approach < 0.0 {
+                        let outgoing = direction - normal * (2.0 * approach);
+                        trans.rotation = outgoing[1].atan2(outgoing[0]);
+                        drop(trans);
+                        if let Some(mut velocity) = world.get_component_mut::<comps::Velocity>(ball)
+                        {
+                            velocity.velocity =
+                                (velocity.velocity * boost).min(config.max_velocity);
+                        }
+                    }
+                }
+                comps::ObstacleKind::Portal { exit } => {
+                    if direction.dot(&offset) < 0.0 {
+                        trans.location = exit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn build_handle_ball_ball_collision() -> Box<dyn Schedulable> {
+    SystemBuilder::new("handle_ball_ball_collision")
+        .read_resource::<comps::CollisionEvents>()
+        .write_component::<comps::Transformation>()
+        .build(handle_ball_ball_collision)
+}
+
+/// Reflects colliding balls at the line connecting their centers. Only a ball
+/// heading towards the other one is reflected.
+fn handle_ball_ball_collision(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    collisions: &mut legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+    _: &mut (),
+) {
+    for one_collision in collisions.started() {
+        if let Some((first, second)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Ball>(world, one_collision)
+        {
+            let location = |world: &SubWorld, ball: Entity| {
+                world
+                    .get_component::<comps::Transformation>(ball)
+                    .unwrap()
+                    .location
+            };
+            let normal = location(world, second) - location(world, first);
+            if normal.norm() == 0.0 {
+                continue;
+            }
+            let normal = normal.normalize();
+            for (ball, towards) in [(first, normal), (second, -normal)] {
+                let mut trans = world
+                    .get_component_mut::<comps::Transformation>(ball)
+                    .unwrap();
+                let direction = na::Vector2::new(trans.rotation.cos(), trans.rotation.sin());
+                let approach = direction.dot(&towards);
+                if approach > 0.0 {
+                    let outgoing = direction - towards * (2.0 * approach);
+                    trans.rotation = outgoing[1].atan2(outgoing[0]);
+                }
+            }
+        }
+    }
+}
+
+pub fn build_rally_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("rally")
+        .read_resource::<comps::CollisionEvents>()
+        .read_resource::<comps::RallyConfig>()
+        .write_resource::<comps::Rally>()
+        .write_component::<comps::Velocity>()
+        .write_component::<comps::LastHit>()
+        .build(rally)
+}
+
+fn rally(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedRead<comps::RallyConfig>,
+        legion::systems::resource::PreparedWrite<comps::Rally>,
+    ),
+    _: &mut (),
+) {
+    let (collisions, config, rally) = resource;
+    for one_collision in collisions.started() {
+        if let Some((ball, barrier)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Barrier>(world, one_collision)
+        {
+            if let Some(mut last_hit) = world.get_component_mut::<comps::LastHit>(ball) {
+                last_hit.barrier = Some(barrier);
+            }
+            rally.hits += 1;
+            if let Some(mut velocity) = world.get_component_mut::<comps::Velocity>(ball) {
+                velocity.velocity = (velocity.velocity * config.speed_up).min(config.max_velocity);
+            }
+        } else if let Some((ball, _)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Goal>(world, one_collision)
+        {
+            if let Some(mut velocity) = world.get_component_mut::<comps::Velocity>(ball) {
+                velocity.velocity = config.serve_velocity;
+            }
+        }
+    }
+}
+
+pub fn build_ai_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("ai")
+        .read_resource::<comps::Arena>()
+        .write_resource::<comps::Rng>()
+        .with_query(
+            <(
+                Read<comps::Transformation>,
+                Read<comps::Velocity>,
+                Read<comps::Hitbox>,
+            )>::query()
+            .filter(tag::<comps::Ball>()),
+        )
+        .with_query(<(
+            Read<comps::Transformation>,
+            Read<comps::Hitbox>,
+            Write<comps::AiController>,
+            Write<comps::PaddleMotion>,
+        )>::query())
+        .build(control_ai)
+}
+
+fn control_ai(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    (arena, rng): &mut (
+        legion::systems::resource::PreparedRead<comps::Arena>,
+        legion::systems::resource::PreparedWrite<comps::Rng>,
+    ),
+    (balls, paddles): &mut (
+        Query<
+            (
+                Read<comps::Transformation>,
+                Read<comps::Velocity>,
+                Read<comps::Hitbox>,
+            ),
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Transformation>,
+                    filter::ComponentFilter<comps::Velocity>,
+                    filter::ComponentFilter<comps::Hitbox>,
+                    filter::TagFilter<comps::Ball>,
+                )>,
+                filter::And<(
+                    filter::Passthrough,
+                    filter::Passthrough,
+                    filter::Passthrough,
+                )>,
+                filter::And<(
+                    filter::Passthrough,
+                    filter::Passthrough,
+                    filter::Passthrough,
+                )>,
+            >,
+        >,
+        Query<
+            (
+                Read<comps::Transformation>,
+                Read<comps::Hitbox>,
+                Write<comps::AiController>,
+                Write<comps::PaddleMotion>,
+            ),
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Transformation>,
+                    filter::ComponentFilter<comps::Hitbox>,
+                    filter::ComponentFilter<comps::AiController>,
+                    filter::ComponentFilter<comps::PaddleMotion>,
+                )>,
+                filter::And<(
+                    filter::Passthrough,
+                    filter::Passthrough,
+                    filter::Passthrough,
+                    filter::Passthrough,
+                )>,
+                filter::And<(
+                    filter::Passthrough,
+                    filter::Passthrough,
+                    filter::Passthrough,
+                    filter::Passthrough,
+                )>,
+            >,
+        >,
+    ),
+) {
+    let ball_states: Vec<(na::Vector2<f32>, na::Vector2<f32>, na::Vector2<f32>)> = balls
+        .iter(world)
+        .map(|(trans, velocity, hitbox)| {
+            let direction = na::Vector2::new(trans.rotation.cos(), trans.rotation.sin());
+            (
+                trans.location,
+                direction * velocity.velocity,
+                hitbox.shape.local_aabb().half_extents(),
+            )
+        })
+        .collect();
+    for (trans, hitbox, mut ai, mut motion) in paddles.iter_mut(world) {
+        // paddles move along their long axis and guard the line across it
+        let half_extents = hitbox.shape.local_aabb().half_extents();
+        let axis = long_axis(half_extents);
+        let across = 1 - axis;
+        ai.countdown = ai.countdown.saturating_sub(1);
+        if ai.countdown == 0 {
+            ai.countdown = ai.reaction_ticks;
+            // react to the ball as it was seen at the previous look
+            ai.target = ai.seen;
+            // follow the ball arriving first, wait in the center if none approaches
+            let intercept = ball_states
+                .iter()
+                .filter_map(|(location, velocity, ball_extents)| {
+                    let ticks = (trans.location[across] - location[across]) / velocity[across];
+                    if ticks.is_finite() && ticks >= 0.0 {
+                        let at = trans.location[across];
+                        Some((
+                            ticks,
+                            predict_crossing(
+                                *location,
+                                *velocity,
+                                *ball_extents,
+                                across,
+                                at,
+                                arena,
+                            ),
+                        ))
+                    } else {
+                        None
+                    }
+                })
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+                .map(|(_, intercept)| intercept);
+            let error = rng.signed() * ai.aim_error;
+            ai.seen = Some(intercept.unwrap_or((arena.min[axis] + arena.max[axis]) / 2.0) + error);
+        }
+        motion.target = match ai.target {
+            Some(target) => {
+                let target = target.clamp(
+                    arena.min[axis] + half_extents[axis],
+                    arena.max[axis] - half_extents[axis],
+                );
+                (target - trans.location[axis]).clamp(-ai.max_speed, ai.max_speed)
+            }
+            None => 0.0,
+        };
+    }
+}
+
+pub fn build_input_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("input")
+        .read_resource::<comps::Inputs>()
+        .with_query(<(
+            Read<comps::Player>,
+            Read<comps::InputController>,
+            Write<comps::PaddleMotion>,
+        )>::query())
+        .build(control_input)
+}
+
+fn control_input(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    inputs: &mut legion::systems::resource::PreparedRead<comps::Inputs>,
+    query: &mut Query<
+        (
+            Read<comps::Player>,
+            Read<comps::InputController>,
+            Write<comps::PaddleMotion>,
+        ),
+        filter::EntityFilterTuple<
+            filter::And<(
+                filter::ComponentFilter<comps::Player>,
+                filter::ComponentFilter<comps::InputController>,
+                filter::ComponentFilter<comps::PaddleMotion>,
+            )>,
+            filter::And<(
+                filter::Passthrough,
+                filter::Passthrough,
+                filter::Passthrough,
+            )>,
+            filter::And<(
+                filter::Passthrough,
+                filter::Passthrough,
+                filter::Passthrough,
+            )>,
+        >,
+    >,
+) {
+    for (player, controller, mut motion) in query.iter_mut(world) {
+        motion.target = match inputs.actions[player.index] {
+            comps::Action::Stay => 0.0,
+            comps::Action::Up => -controller.max_speed,
+            comps::Action::Down => controller.max_speed,
+        };
+    }
+}
+
+pub fn build_paddle_physics_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("paddle_physics")
+        .read_resource::<comps::PaddlePhysics>()
+        .read_resource::<comps::Arena>()
+        .with_query(<(
+            Write<comps::Transformation>,
+            Read<comps::Hitbox>,
+            Write<comps::PaddleMotion>,
+        )>::query())
+        .build(move_paddles)
+}
+
+/// Accelerates paddles towards the velocity their controller targets, a
+/// paddle without a target slows down by friction. Paddles stop at the
+/// bounds of the arena.
+fn move_paddles(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    (physics, arena): &mut (
+        legion::systems::resource::PreparedRead<comps::PaddlePhysics>,
+        legion::systems::resource::PreparedRead<comps::Arena>,
+    ),
+    query: &mut Query<
+        (
+            Write<comps::Transformation>,
+            Read<comps::Hitbox>,
+            Write<comps::PaddleMotion>,
+        ),
+        filter::EntityFilterTuple<
+            filter::And<(
+                filter::ComponentFilter<comps::Transformation>,
+                filter::ComponentFilter<comps::Hitbox>,
+                filter::ComponentFilter<comps::PaddleMotion>,
+            )>,
+            filter::And<(
+                filter::Passthrough,
+                filter::Passthrough,
+                filter::Passthrough,
+            )>,
+            filter::And<(
+                filter::Passthrough,
+                filter::Passthrough,
+                filter::Passthrough,
+            )>,
+        >,
+    >,
+) {
+    for (mut trans, hitbox, mut motion) in query.iter_mut(world) {
+        let half_extents = hitbox.shape.local_aabb().half_extents();
+        // up and down move horizontal paddles to the left and right
+        let axis = long_axis(half_extents);
+        let change = if motion.target == 0.0 {
+            physics.friction
+        } else {
+            physics.acceleration
+        };
+        motion.velocity += (motion.target - motion.velocity).clamp(-change, change);
+        let min = arena.min[axis] + half_extents[axis];
+        let max = arena.max[axis] - half_extents[axis];
+        let location = trans.location[axis] + motion.velocity;
+        if location <= min || location >= max {
+            motion.velocity = 0.0;
+        }
+        trans.location[axis] = location.clamp(min, max);
+    }
+}
+
+pub fn build_spin_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("spin")
+        .read_resource::<comps::BounceConfig>()
+        .with_query(
+            <(Write<comps::Transformation>, Write<comps::Spin>)>::query()
+                .filter(tag::<comps::Ball>()),
+        )
+        .build(curve_balls)
+}
+
+/// Curves the path of spinning balls, the spin decays over time
+fn curve_balls(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    config: &mut legion::systems::resource::PreparedRead<comps::BounceConfig>,
+    query: &mut Query<
+        (Write<comps::Transformation>, Write<comps::Spin>),
+        filter::EntityFilterTuple<
+            filter::And<(
+                filter::ComponentFilter<comps::Transformation>,
+                filter::ComponentFilter<comps::Spin>,
+                filter::TagFilter<comps::Ball>,
+            )>,
+            filter::And<(filter::Passthrough, filter::Passthrough)>,
+            filter::And<(filter::Passthrough, filter::Passthrough)>,
+        >,
+    >,
+) {
+    for (mut trans, mut spin) in query.iter_mut(world) {
+        trans.rotation += spin.angular_velocity;
+        spin.angular_velocity *= config.spin_decay;
+    }
+}
+
+/// Predicts the y coordinate at which a ball crosses `x`, taking reflections
+/// on the upper and lower walls of `arena` into account.
+pub fn predict_intercept(
+    location: na::Vector2<f32>,
+    velocity: na::Vector2<f32>,
+    half_extents: na::Vector2<f32>,
+    x: f32,
+    arena: &comps::Arena,
+) -> f32 {
+    predict_crossing(location, velocity, half_extents, 0, x, arena)
+}
+
+/// Predicts the coordinate at which a ball crosses the line at `at` on the
+/// `across` axis, taking reflections at the bounds of `arena` along the line
+/// into account. The ball bounces once its edge, not its center, reaches a
+/// bound.
+pub fn predict_crossing(
+    location: na::Vector2<f32>,
+    velocity: na::Vector2<f32>,
+    half_extents: na::Vector2<f32>,
+    across: usize,
+    at: f32,
+    arena: &comps::Arena,
+) -> f32 {
+    let along = 1 - across;
+    let min = arena.min[along] + half_extents[along];
+    let max = arena.max[along] - half_extents[along];
+    let ticks = (at - location[across]) / velocity[across];
+    let length = max - min;
+    if length <= 0.0 {
+        return (min + max) / 2.0;
+    }
+    let unfolded = (location[along] + velocity[along] * ticks - min).rem_euclid(2.0 * length);
+    if unfolded > length {
+        max - (unfolded - length)
+    } else {
+        min + unfolded
+    }
+}
+
+/// Wraps `systems` into a schedule step, which only executes them while the
+/// `GameState` resource is one of `states`.
+pub fn run_in_states(
+    states: &'static [comps::GameState],
+    systems: Vec<Box<dyn Schedulable>>,
+) -> impl FnMut(&mut World, &mut Resources) {
+    let mut executor = Executor::new(systems);
+    move |world, resources| {
+        let active = states.contains(&*resources.get::<comps::GameState>().unwrap());
+        if active {
+            executor.execute(world, resources);
+        }
+    }
+}
+
+pub fn build_multi_ball_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("multi_ball")
+        .read_resource::<comps::MultiBallConfig>()
+        .read_resource::<comps::MatchConfig>()
+        .read_resource::<comps::RallyConfig>()
+        .read_resource::<comps::Rally>()
+        .write_resource::<comps::MultiBall>()
+        .write_resource::<comps::Rng>()
+        .with_query(
+            <(Read<comps::RenderShape>, Read<comps::Hitbox>)>::query().filter(tag::<comps::Ball>()),
+        )
+        .build(spawn_balls)
+}
+
+/// Serves another ball after every `hits_per_ball` hits of the rally and
+/// every `spawn_ticks` ticks, as long as less than `max_balls` are in play.
+/// Balls requested by power-ups are served one per tick.
+fn spawn_balls(
+    commands: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::MultiBallConfig>,
+        legion::systems::resource::PreparedRead<comps::MatchConfig>,
+        legion::systems::resource::PreparedRead<comps::RallyConfig>,
+        legion::systems::resource::PreparedRead<comps::Rally>,
+        legion::systems::resource::PreparedWrite<comps::MultiBall>,
+        legion::systems::resource::PreparedWrite<comps::Rng>,
+    ),
+    query: &mut Query<
+        (Read<comps::RenderShape>, Read<comps::Hitbox>),
+        filter::EntityFilterTuple<
+            filter::And<(
+                filter::ComponentFilter<comps::RenderShape>,
+                filter::ComponentFilter<comps::Hitbox>,
+                filter::TagFilter<comps::Ball>,
+            )>,
+            filter::And<(filter::Passthrough, filter::Passthrough)>,
+            filter::And<(filter::Passthrough, filter::Passthrough)>,
+        >,
+    >,
+) {
+    let (config, match_config, rally_config, rally, multi_ball, rng) = resource;
+    let by_hits =
+        config.hits_per_ball > 0 && rally.hits >= (multi_ball.spawned + 1) * config.hits_per_ball;
+    let mut by_timer = false;
+    if config.spawn_ticks > 0 {
+        multi_ball.countdown = multi_ball.countdown.saturating_sub(1);
+        by_timer = multi_ball.countdown == 0;
+    }
+    let requested = multi_ball.requested > 0;
+    let served = by_hits || by_timer;
+    if served {
+        multi_ball.countdown = config.spawn_ticks;
+    } else if requested {
+        multi_ball.requested -= 1;
+    } else {
+        return;
+    }
+    let balls: Vec<(
+        comps::RenderShape,
+        std::sync::Arc<dyn ncollide2d::shape::Shape<f32>>,
+    )> = query
+        .iter(world)
+        .map(|(shape, hitbox)| (*shape, hitbox.shape.clone()))
+        .collect();
+    if !requested && balls.len() >= config.max_balls {
+        return;
+    }
+    let (render_shape, shape) = match balls.into_iter().next() {
+        Some(ball) => ball,
+        None => return,
+    };
+    // only balls actually served raise the hits needed for the next one
+    if served {
+        multi_ball.spawned += 1;
+    }
+    let towards = if rng.signed() < 0.0 {
+        std::f32::consts::PI
+    } else {
+        0.0
+    };
+    let rotation = towards + rng.signed() * match_config.serve_spread;
+    commands.insert(
+        (comps::Ball, ()),
+        vec![(
+            comps::Transformation {
+                location: match_config.serve_location,
+                rotation,
+                scale: na::Vector2::new(1.0, 1.0),
+            },
+            render_shape,
+            comps::Velocity {
+                velocity: rally_config.serve_velocity,
+            },
+            comps::Hitbox {
+                shape,
+                slap_handle: None,
+            },
+            comps::LastHit { barrier: None },
+            comps::Effects::default(),
+            comps::Spin::default(),
+            comps::CollisionLayer::ball(),
+        )],
+    );
+}
+
+/// Factor applied to the length of the paddle of the collecting player
+const BIGGER_PADDLE: f32 = 1.5;
+/// Factor applied to the velocity of the collecting ball
+const FASTER_BALL: f32 = 1.5;
+/// Factor applied to the speed of the paddles of the opponents
+const SLOW_OPPONENT: f32 = 0.5;
+
+pub fn build_power_up_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("power_up")
+        .read_resource::<comps::CollisionEvents>()
+        .read_resource::<comps::PowerUpConfig>()
+        .read_resource::<comps::RallyConfig>()
+        .read_resource::<comps::Arena>()
+        .write_resource::<comps::PowerUps>()
+        .write_resource::<comps::MultiBall>()
+        .write_resource::<comps::Rng>()
+        .write_resource::<world::CollisionWorld<f32, ()>>()
+        .write_resource::<std::collections::HashMap<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            legion::entity::Entity,
+        >>()
+        .write_resource::<events::Events<comps::PowerUpCollected>>()
+        .read_component::<comps::PowerUpKind>()
+        .read_component::<comps::LastHit>()
+        .read_component::<comps::Player>()
+        .write_component::<comps::Effects>()
+        .write_component::<comps::Hitbox>()
+        .write_component::<comps::RenderShape>()
+        .write_component::<comps::Velocity>()
+        .write_component::<comps::Stuck>()
+        .write_component::<comps::AiController>()
+        .write_component::<comps::InputController>()
+        .with_query(<Read<comps::PowerUpKind>>::query().filter(tag::<comps::PowerUp>()))
+        .with_query(<Read<comps::Player>>::query().filter(tag::<comps::Barrier>()))
+        .build(collect_power_ups)
+}
+
+/// Applies the effect of every power-up touched by a ball on behalf of the
+/// player who hit the ball last and spawns new power-ups every `spawn_ticks`
+fn collect_power_ups(
+    commands: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedRead<comps::PowerUpConfig>,
+        legion::systems::resource::PreparedRead<comps::RallyConfig>,
+        legion::systems::resource::PreparedRead<comps::Arena>,
+        legion::systems::resource::PreparedWrite<comps::PowerUps>,
+        legion::systems::resource::PreparedWrite<comps::MultiBall>,
+        legion::systems::resource::PreparedWrite<comps::Rng>,
+        legion::systems::resource::PreparedWrite<
+            ncollide2d::pipeline::world::CollisionWorld<f32, ()>,
+        >,
+        legion::systems::resource::PreparedWrite<
+            std::collections::HashMap<
+                ncollide2d::pipeline::CollisionObjectSlabHandle,
+                legion::entity::Entity,
+            >,
+        >,
+        legion::systems::resource::PreparedWrite<events::Events<comps::PowerUpCollected>>,
+    ),
+    (power_up_query, paddles): &mut (
+        Query<
+            Read<comps::PowerUpKind>,
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::PowerUpKind>,
+                    filter::TagFilter<comps::PowerUp>,
+                )>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+        Query<
+            Read<comps::Player>,
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Player>,
+                    filter::TagFilter<comps::Barrier>,
+                )>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+    ),
+) {
+    let (
+        collisions,
+        config,
+        rally_config,
+        arena,
+        power_ups,
+        multi_ball,
+        rng,
+        co_world,
+        map,
+        collected_events,
+    ) = resource;
+    let mut collected = Vec::new();
+    for one_collision in collisions.started() {
+        if let Some((ball, power_up)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::PowerUp>(world, one_collision)
+        {
+            if collected.contains(&power_up) {
+                continue;
+            }
+            // a power-up is left in place until someone hit the ball
+            let hitter = world
+                .get_component::<comps::LastHit>(ball)
+                .and_then(|last_hit| last_hit.barrier)
+                .and_then(|barrier| {
+                    world
+                        .get_component::<comps::Player>(barrier)
+                        .map(|player| (barrier, player.index))
+                });
+            let (paddle, player) = match hitter {
+                Some(hitter) => hitter,
+                None => continue,
+            };
+            let kind = *world.get_component::<comps::PowerUpKind>(power_up).unwrap();
+            let targets = match kind {
+                comps::PowerUpKind::BiggerPaddle | comps::PowerUpKind::StickyPaddle => vec![paddle],
+                comps::PowerUpKind::FasterBall => vec![ball],
+                comps::PowerUpKind::SlowOpponent => paddles
+                    .iter_entities(world)
+                    .filter(|(_, other)| other.index != player)
+                    .map(|(entity, _)| entity)
+                    .collect(),
+                comps::PowerUpKind::MultiBall => {
+                    multi_ball.requested += 1;
+                    Vec::new()
+                }
+            };
+            for target in targets {
+                add_effect(
+                    world,
+                    co_world,
+                    target,
+                    kind,
+                    config.effect_ticks,
+                    rally_config.max_velocity,
+                );
+            }
+            collected_events.send(comps::PowerUpCollected { kind, player });
+            remove_from_play(commands, world, co_world, map, power_up);
+            collected.push(power_up);
+        }
+    }
+
+    if config.spawn_ticks == 0 {
+        return;
+    }
+    power_ups.countdown = power_ups.countdown.saturating_sub(1);
+    if power_ups.countdown > 0 {
+        return;
+    }
+    power_ups.countdown = config.spawn_ticks;
+    let remaining = power_up_query.iter(world).count() - collected.len();
+    if remaining >= config.max_power_ups {
+        return;
+    }
+    let kind =
+        comps::PowerUpKind::ALL[(rng.next_u64() % comps::PowerUpKind::ALL.len() as u64) as usize];
+    // power-ups appear in the middle half of the arena, clear of the paddles
+    let center = (arena.min + arena.max) / 2.0;
+    let spread = (arena.max - arena.min) / 2.0 - config.half_extents;
+    let location =
+        center + na::Vector2::new(rng.signed() * spread[0] / 2.0, rng.signed() * spread[1]);
+    commands.insert(
+        (comps::PowerUp, ()),
+        vec![(
+            comps::Transformation {
+                location,
+                rotation: 0.0,
+                scale: na::Vector2::new(1.0, 1.0),
+            },
+            comps::RenderShape {
+                color: kind.color(),
+                half_extents: config.half_extents,
+            },
+            comps::Hitbox {
+                shape: std::sync::Arc::new(ncollide2d::shape::Cuboid::new(config.half_extents)),
+                slap_handle: None,
+            },
+            kind,
+            comps::CollisionLayer::fixed(),
+        )],
+    );
+}
+
+/// Starts an effect on `entity`, collecting an active effect again only extends it.
+/// A faster ball is not sped up beyond `max_velocity`.
+fn add_effect(
+    world: &mut SubWorld,
+    co_world: &mut world::CollisionWorld<f32, ()>,
+    entity: Entity,
+    kind: comps::PowerUpKind,
+    ticks: u32,
+    max_velocity: f32,
+) {
+    match world.get_component_mut::<comps::Effects>(entity) {
+        Some(mut effects) => {
+            if let Some(effect) = effects.active.iter_mut().find(|effect| effect.kind == kind) {
+                effect.countdown = ticks;
+                return;
+            }
+        }
+        None => return,
+    }
+    let delta = change_effect_value(world, co_world, entity, kind, |value| match kind {
+        comps::PowerUpKind::BiggerPaddle => value * BIGGER_PADDLE,
+        comps::PowerUpKind::FasterBall => (value * FASTER_BALL).min(max_velocity.max(value)),
+        comps::PowerUpKind::SlowOpponent => value * SLOW_OPPONENT,
+        comps::PowerUpKind::MultiBall | comps::PowerUpKind::StickyPaddle => value,
+    });
+    world
+        .get_component_mut::<comps::Effects>(entity)
+        .unwrap()
+        .active
+        .push(comps::Effect {
+            kind,
+            countdown: ticks,
+            delta,
+        });
+}
+
+/// Reverts an ended effect by taking back its change, so changes made by
+/// others in the meantime, like a ball speeding up during a rally, are kept
+fn revert_effect(
+    world: &mut SubWorld,
+    co_world: &mut world::CollisionWorld<f32, ()>,
+    entity: Entity,
+    effect: comps::Effect,
+) {
+    change_effect_value(world, co_world, entity, effect.kind, |value| {
+        (value - effect.delta).max(0.0)
+    });
+}
+
+/// Maps the value an effect of `kind` acts on by `change` and returns the
+/// difference. A stuck ball keeps its velocity in `Stuck` until released.
+fn change_effect_value(
+    world: &mut SubWorld,
+    co_world: &mut world::CollisionWorld<f32, ()>,
+    entity: Entity,
+    kind: comps::PowerUpKind,
+    change: impl Fn(f32) -> f32,
+) -> f32 {
+    let mut delta = 0.0;
+    let mut apply = |value: &mut f32| {
+        let changed = change(*value);
+        delta = changed - *value;
+        *value = changed;
+    };
+    match kind {
+        comps::PowerUpKind::BiggerPaddle => {
+            let mut half_extents = None;
+            if let Some(mut hitbox) = world.get_component_mut::<comps::Hitbox>(entity) {
+                let mut changed = hitbox.shape.local_aabb().half_extents();
+                let axis = long_axis(changed);
+                apply(&mut changed[axis]);
+                hitbox.shape = std::sync::Arc::new(ncollide2d::shape::Cuboid::new(changed));
+                if let Some(co) = hitbox
+                    .slap_handle
+                    .and_then(|handle| co_world.objects.get_mut(handle))
+                {
+                    co.set_shape(ncollide2d::shape::ShapeHandle::from_arc(
+                        hitbox.shape.clone(),
+                    ));
+                }
+                half_extents = Some(changed);
+            }
+            if let Some(mut shape) = world.get_component_mut::<comps::RenderShape>(entity) {
+                match half_extents {
+                    Some(half_extents) => shape.half_extents = half_extents,
+                    None => {
+                        let axis = long_axis(shape.half_extents);
+                        apply(&mut shape.half_extents[axis]);
+                    }
+                }
+            }
+        }
+        comps::PowerUpKind::FasterBall => {
+            if world.get_component::<comps::Stuck>(entity).is_some() {
+                apply(
+                    &mut world
+                        .get_component_mut::<comps::Stuck>(entity)
+                        .unwrap()
+                        .velocity,
+                );
+            } else if let Some(mut velocity) = world.get_component_mut::<comps::Velocity>(entity) {
+                apply(&mut velocity.velocity);
+            }
+        }
+        comps::PowerUpKind::SlowOpponent => {
+            if let Some(mut ai) = world.get_component_mut::<comps::AiController>(entity) {
+                apply(&mut ai.max_speed);
+            }
+            if let Some(mut input) = world.get_component_mut::<comps::InputController>(entity) {
+                apply(&mut input.max_speed);
+            }
         }
+        comps::PowerUpKind::MultiBall | comps::PowerUpKind::StickyPaddle => (),
+    }
+    delta
+}
+
+fn long_axis(half_extents: na::Vector2<f32>) -> usize {
+    if half_extents[1] >= half_extents[0] {
+        1
+    } else {
+        0
+    }
+}
+
+pub fn build_effects_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("effects")
+        .read_resource::<comps::CollisionEvents>()
+        .read_resource::<comps::PowerUpConfig>()
+        .write_resource::<world::CollisionWorld<f32, ()>>()
+        .read_component::<comps::LastHit>()
+        .write_component::<comps::Stuck>()
+        .write_component::<comps::Transformation>()
+        .write_component::<comps::Velocity>()
+        .write_component::<comps::Hitbox>()
+        .write_component::<comps::RenderShape>()
+        .write_component::<comps::AiController>()
+        .write_component::<comps::InputController>()
+        .with_query(<Write<comps::Effects>>::query())
+        .with_query(<Read<comps::Stuck>>::query())
+        .build(run_effects)
+}
+
+/// Counts down the active effects and reverts the ended ones. Balls hitting a
+/// sticky paddle are held in place relative to it and released with the
+/// velocity they arrived with.
+fn run_effects(
+    commands: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedRead<comps::PowerUpConfig>,
+        legion::systems::resource::PreparedWrite<
+            ncollide2d::pipeline::world::CollisionWorld<f32, ()>,
+        >,
+    ),
+    (effects_query, stuck_query): &mut (
+        Query<
+            Write<comps::Effects>,
+            filter::EntityFilterTuple<
+                filter::ComponentFilter<comps::Effects>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+        Query<
+            Read<comps::Stuck>,
+            filter::EntityFilterTuple<
+                filter::ComponentFilter<comps::Stuck>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+    ),
+) {
+    let (collisions, config, co_world) = resource;
+    for one_collision in collisions.started() {
+        if let Some((ball, barrier)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Barrier>(world, one_collision)
+        {
+            let sticky = world
+                .get_component::<comps::Effects>(barrier)
+                .is_some_and(|effects| effects.contains(comps::PowerUpKind::StickyPaddle));
+            if !sticky || world.get_component::<comps::Stuck>(ball).is_some() {
+                continue;
+            }
+            let location = |world: &SubWorld, entity: Entity| {
+                world
+                    .get_component::<comps::Transformation>(entity)
+                    .unwrap()
+                    .location
+            };
+            let offset = location(world, ball) - location(world, barrier);
+            if let Some(mut velocity) = world.get_component_mut::<comps::Velocity>(ball) {
+                commands.add_component(
+                    ball,
+                    comps::Stuck {
+                        offset,
+                        countdown: config.stick_ticks,
+                        velocity: velocity.velocity,
+                    },
+                );
+                velocity.velocity = 0.0;
+            }
+        }
+    }
+
+    let stuck: Vec<Entity> = stuck_query
+        .iter_entities(world)
+        .map(|(entity, _)| entity)
+        .collect();
+    for ball in stuck {
+        let paddle_location = world
+            .get_component::<comps::LastHit>(ball)
+            .and_then(|last_hit| last_hit.barrier)
+            .and_then(|paddle| world.get_component::<comps::Transformation>(paddle))
+            .map(|trans| trans.location);
+        let stuck = {
+            let mut stuck = world.get_component_mut::<comps::Stuck>(ball).unwrap();
+            stuck.countdown = stuck.countdown.saturating_sub(1);
+            *stuck
+        };
+        if let Some(paddle_location) = paddle_location {
+            if let Some(mut trans) = world.get_component_mut::<comps::Transformation>(ball) {
+                trans.location = paddle_location + stuck.offset;
+            }
+        }
+        if stuck.countdown == 0 {
+            if let Some(mut velocity) = world.get_component_mut::<comps::Velocity>(ball) {
+                velocity.velocity = stuck.velocity;
+            }
+            commands.remove_component::<comps::Stuck>(ball);
+        }
+    }
+
+    let mut ended = Vec::new();
+    for (entity, mut effects) in effects_query.iter_entities_mut(world) {
+        for effect in effects.active.iter_mut() {
+            effect.countdown = effect.countdown.saturating_sub(1);
+            if effect.countdown == 0 {
+                ended.push((entity, *effect));
+            }
+        }
+        effects.active.retain(|effect| effect.countdown > 0);
+    }
+    for (entity, effect) in ended {
+        revert_effect(world, co_world, entity, effect);
+    }
+}
+
+pub fn build_score_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("score")
+        .read_resource::<comps::CollisionEvents>()
+        .read_resource::<comps::MatchConfig>()
+        .write_resource::<comps::Match>()
+        .write_resource::<comps::GameState>()
+        .write_resource::<world::CollisionWorld<f32, ()>>()
+        .write_resource::<std::collections::HashMap<
+            ncollide2d::pipeline::CollisionObjectSlabHandle,
+            legion::entity::Entity,
+        >>()
+        .write_resource::<events::Events<comps::GoalScored>>()
+        .read_component::<comps::Player>()
+        .read_component::<comps::Hitbox>()
+        .read_component::<comps::LastHit>()
+        .with_query(<Read<comps::Velocity>>::query().filter(tag::<comps::Ball>()))
+        .with_query(<Read<comps::Player>>::query().filter(tag::<comps::Goal>()))
+        .with_query(<Read<comps::Player>>::query().filter(tag::<comps::Barrier>()))
+        .build(score)
+}
+
+/// Awards a point for every ball in a goal to the player who hit it last. An
+/// own goal or a goal without any hit is awarded to the opponent, if only two
+/// players are left. The point ends once the last ball scored, any other ball
+/// is removed from play.
+///
+/// With `lives` configured, players conceding that many goals are eliminated.
+/// Their goal turns into a wall and their paddle is removed.
+fn score(
+    commands: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::CollisionEvents>,
+        legion::systems::resource::PreparedRead<comps::MatchConfig>,
+        legion::systems::resource::PreparedWrite<comps::Match>,
+        legion::systems::resource::PreparedWrite<comps::GameState>,
+        legion::systems::resource::PreparedWrite<
+            ncollide2d::pipeline::world::CollisionWorld<f32, ()>,
+        >,
+        legion::systems::resource::PreparedWrite<
+            std::collections::HashMap<
+                ncollide2d::pipeline::CollisionObjectSlabHandle,
+                legion::entity::Entity,
+            >,
+        >,
+        legion::systems::resource::PreparedWrite<events::Events<comps::GoalScored>>,
+    ),
+    (balls_query, goals_query, paddles_query): &mut (
+        Query<
+            Read<comps::Velocity>,
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Velocity>,
+                    filter::TagFilter<comps::Ball>,
+                )>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+        Query<
+            Read<comps::Player>,
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Player>,
+                    filter::TagFilter<comps::Goal>,
+                )>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+        Query<
+            Read<comps::Player>,
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Player>,
+                    filter::TagFilter<comps::Barrier>,
+                )>,
+                filter::Passthrough,
+                filter::Passthrough,
+            >,
+        >,
+    ),
+) {
+    let (collisions, config, game, state, co_world, map, goals) = resource;
+    let mut balls = balls_query.iter(world).count();
+    let mut active: Vec<usize> = goals_query.iter(world).map(|player| player.index).collect();
+    active.sort_unstable();
+    let mut removed = Vec::new();
+    for one_collision in collisions.started() {
+        if let Some((ball, goal)) =
+            sort_collision_pair_by_tag::<comps::Ball, comps::Goal>(world, one_collision)
+        {
+            let conceder = world.get_component::<comps::Player>(goal).unwrap().index;
+            if removed.contains(&ball) || !active.contains(&conceder) {
+                continue;
+            }
+            let hitter = world
+                .get_component::<comps::LastHit>(ball)
+                .and_then(|last_hit| last_hit.barrier)
+                .and_then(|barrier| world.get_component::<comps::Player>(barrier))
+                .map(|player| player.index)
+                .filter(|hitter| *hitter != conceder && active.contains(hitter));
+            let scorer = match hitter {
+                Some(hitter) => Some(hitter),
+                None if active.len() == 2 => {
+                    active.iter().copied().find(|index| *index != conceder)
+                }
+                None => None,
+            };
+            if let Some(scorer) = scorer {
+                game.score[scorer] += 1;
+            }
+            game.conceded[conceder] += 1;
+            game.receiver = conceder;
+            goals.send(comps::GoalScored { scorer, conceder });
+
+            if config.lives == 0 {
+                if let Some(scorer) = scorer {
+                    let own = game.score[scorer];
+                    let other = active
+                        .iter()
+                        .filter(|index| **index != scorer)
+                        .map(|index| game.score[*index])
+                        .max()
+                        .unwrap_or(0);
+                    if own >= config.points_to_win && own >= other + config.win_by {
+                        **state = comps::GameState::GameOver;
+                        return;
+                    }
+                }
+            } else if game.conceded[conceder] >= config.lives {
+                commands.remove_tag::<comps::Goal>(goal);
+                commands.add_tag(goal, comps::Wall);
+                let half_extents = world
+                    .get_component::<comps::Hitbox>(goal)
+                    .map(|hitbox| hitbox.shape.local_aabb().half_extents());
+                if let Some(half_extents) = half_extents {
+                    commands.add_component(
+                        goal,
+                        comps::RenderShape {
+                            color: [1.0, 1.0, 1.0],
+                            half_extents,
+                        },
+                    );
+                }
+                let paddles: Vec<Entity> = paddles_query
+                    .iter_entities(world)
+                    .filter(|(_, player)| player.index == conceder)
+                    .map(|(entity, _)| entity)
+                    .collect();
+                for paddle in paddles {
+                    remove_from_play(commands, world, co_world, map, paddle);
+                }
+                let position = active.iter().position(|index| *index == conceder).unwrap();
+                active.remove(position);
+                if active.len() <= 1 {
+                    **state = comps::GameState::GameOver;
+                    return;
+                }
+                // the next serve goes to the following player still in the match
+                game.receiver = active[position % active.len()];
+            }
+
+            if balls > 1 {
+                remove_from_play(commands, world, co_world, map, ball);
+                removed.push(ball);
+                balls -= 1;
+            } else {
+                **state = comps::GameState::PointScored;
+                game.countdown = config.point_ticks;
+                return;
+            }
+        }
+    }
+}
+
+/// Deletes `entity` and removes its collision object
+fn remove_from_play(
+    commands: &mut CommandBuffer,
+    world: &SubWorld,
+    co_world: &mut world::CollisionWorld<f32, ()>,
+    map: &mut std::collections::HashMap<ncollide2d::pipeline::CollisionObjectSlabHandle, Entity>,
+    entity: Entity,
+) {
+    let handle = world
+        .get_component::<comps::Hitbox>(entity)
+        .and_then(|hitbox| hitbox.slap_handle);
+    if let Some(handle) = handle {
+        co_world.remove(&[handle]);
+        map.remove(&handle);
+    }
+    commands.delete(entity);
+}
+
+pub fn build_match_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("match")
+        .read_resource::<comps::MatchConfig>()
+        .read_resource::<comps::RallyConfig>()
+        .write_resource::<comps::Match>()
+        .write_resource::<comps::GameState>()
+        .write_resource::<comps::Rally>()
+        .write_resource::<comps::Rng>()
+        .read_resource::<comps::MultiBallConfig>()
+        .write_resource::<comps::MultiBall>()
+        .write_component::<comps::LastHit>()
+        .write_component::<comps::Effects>()
+        .write_component::<comps::Spin>()
+        .with_query(
+            <(Write<comps::Transformation>, Write<comps::Velocity>)>::query()
+                .filter(tag::<comps::Ball>()),
+        )
+        .with_query(
+            <(Read<comps::Player>, Read<comps::Transformation>)>::query()
+                .filter(tag::<comps::Goal>()),
+        )
+        .build(advance_match)
+}
+
+fn advance_match(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::MatchConfig>,
+        legion::systems::resource::PreparedRead<comps::RallyConfig>,
+        legion::systems::resource::PreparedWrite<comps::Match>,
+        legion::systems::resource::PreparedWrite<comps::GameState>,
+        legion::systems::resource::PreparedWrite<comps::Rally>,
+        legion::systems::resource::PreparedWrite<comps::Rng>,
+        legion::systems::resource::PreparedRead<comps::MultiBallConfig>,
+        legion::systems::resource::PreparedWrite<comps::MultiBall>,
+    ),
+    (query, goals): &mut (
+        Query<
+            (Write<comps::Transformation>, Write<comps::Velocity>),
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Transformation>,
+                    filter::ComponentFilter<comps::Velocity>,
+                    filter::TagFilter<comps::Ball>,
+                )>,
+                filter::And<(filter::Passthrough, filter::Passthrough)>,
+                filter::And<(filter::Passthrough, filter::Passthrough)>,
+            >,
+        >,
+        Query<
+            (Read<comps::Player>, Read<comps::Transformation>),
+            filter::EntityFilterTuple<
+                filter::And<(
+                    filter::ComponentFilter<comps::Player>,
+                    filter::ComponentFilter<comps::Transformation>,
+                    filter::TagFilter<comps::Goal>,
+                )>,
+                filter::And<(filter::Passthrough, filter::Passthrough)>,
+                filter::And<(filter::Passthrough, filter::Passthrough)>,
+            >,
+        >,
+    ),
+) {
+    let (config, rally_config, game, state, rally, rng, multi_ball_config, multi_ball) = resource;
+    match **state {
+        comps::GameState::PointScored => {
+            game.countdown = game.countdown.saturating_sub(1);
+            if game.countdown == 0 {
+                **state = comps::GameState::Serve;
+                game.countdown = config.serve_ticks;
+            }
+        }
+        comps::GameState::Serve => {
+            game.countdown = game.countdown.saturating_sub(1);
+            let served = game.countdown == 0;
+            // balls are served towards the goal of the receiver
+            let towards = goals
+                .iter(world)
+                .find(|(player, _)| player.index == game.receiver)
+                .map_or(0.0, |(_, trans)| {
+                    let direction = trans.location - config.serve_location;
+                    direction[1].atan2(direction[0])
+                });
+            let mut balls = Vec::new();
+            for (ball, (mut trans, mut velocity)) in query.iter_entities_mut(world) {
+                balls.push(ball);
+                trans.location = config.serve_location;
+                if served {
+                    trans.rotation = towards + rng.signed() * config.serve_spread;
+                    velocity.velocity = rally_config.serve_velocity;
+                }
+            }
+            if served {
+                for ball in balls {
+                    if let Some(mut last_hit) = world.get_component_mut::<comps::LastHit>(ball) {
+                        last_hit.barrier = None;
+                    }
+                    if let Some(mut effects) = world.get_component_mut::<comps::Effects>(ball) {
+                        effects.active.clear();
+                    }
+                    if let Some(mut spin) = world.get_component_mut::<comps::Spin>(ball) {
+                        spin.angular_velocity = 0.0;
+                    }
+                }
+                rally.hits = 0;
+                multi_ball.spawned = 0;
+                multi_ball.countdown = multi_ball_config.spawn_ticks;
+                **state = comps::GameState::Playing;
+            }
+        }
+        comps::GameState::Playing | comps::GameState::Paused | comps::GameState::GameOver => (),
     }
 }
 
@@ -255,3 +2071,92 @@ pub fn dispatch_render(
         });
     }
 }
+
+pub fn build_debug_draw_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("debug_draw")
+        .read_component::<comps::Hitbox>()
+        .read_resource::<comps::DebugDraw>()
+        .read_resource::<world::CollisionWorld<f32, ()>>()
+        .read_resource::<std::vec::Vec<[Entity; 2]>>()
+        .write_resource::<Vec<comps::DebugLine>>()
+        .with_query(<Read<comps::Hitbox>>::query())
+        .build(debug_draw)
+}
+
+pub fn debug_draw(
+    _: &mut CommandBuffer,
+    world: &mut SubWorld,
+    resource: &mut (
+        legion::systems::resource::PreparedRead<comps::DebugDraw>,
+        legion::systems::resource::PreparedRead<
+            ncollide2d::pipeline::world::CollisionWorld<f32, ()>,
+        >,
+        legion::systems::resource::PreparedRead<std::vec::Vec<[Entity; 2]>>,
+        legion::systems::resource::PreparedWrite<Vec<comps::DebugLine>>,
+    ),
+    query: &mut Query<
+        Read<comps::Hitbox>,
+        filter::EntityFilterTuple<
+            filter::ComponentFilter<comps::Hitbox>,
+            filter::Passthrough,
+            filter::Passthrough,
+        >,
+    >,
+) {
+    let (debug, co_world, colliders, lines) = resource;
+    lines.clear();
+    if !debug.enabled {
+        return;
+    }
+    let mut centers = std::collections::HashMap::<Entity, na::Vector2<f32>>::new();
+    for (entity, hitbox) in query.iter_entities(world) {
+        let co = match hitbox
+            .slap_handle
+            .and_then(|handle| co_world.objects.get(handle))
+        {
+            Some(co) => co,
+            None => continue,
+        };
+        let color = if colliders.iter().any(|pair| pair.contains(&entity)) {
+            debug.intersecting_color
+        } else {
+            debug.color
+        };
+        // outline the aabb
+        let aabb = co.shape().aabb(co.position());
+        let (mins, maxs) = (aabb.mins().coords, aabb.maxs().coords);
+        let corners = [
+            mins,
+            na::Vector2::new(maxs[0], mins[1]),
+            maxs,
+            na::Vector2::new(mins[0], maxs[1]),
+        ];
+        for idx in 0..corners.len() {
+            lines.push(comps::DebugLine {
+                from: corners[idx],
+                to: corners[(idx + 1) % corners.len()],
+                color,
+            });
+        }
+        // mark the position with a cross
+        let center = co.position().translation.vector;
+        for offset in &[na::Vector2::new(3.0, 3.0), na::Vector2::new(3.0, -3.0)] {
+            lines.push(comps::DebugLine {
+                from: center - offset,
+                to: center + offset,
+                color,
+            });
+        }
+        centers.insert(entity, center);
+    }
+    // connect intersecting pairs
+    for pair in colliders.iter() {
+        if let (Some(first), Some(second)) = (centers.get(&pair[0]), centers.get(&pair[1])) {
+            lines.push(comps::DebugLine {
+                from: *first,
+                to: *second,
+                color: debug.intersecting_color,
+            });
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use super::components;
+use super::scene;
+use legion::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// AI configuration taking part in a tournament
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entrant {
+    pub name: String,
+    pub reaction_ticks: u32,
+    pub max_speed: f32,
+    pub aim_error: f32,
+}
+
+impl Entrant {
+    pub fn with_difficulty(name: &str, difficulty: components::Difficulty) -> Entrant {
+        let ai = components::AiController::with_difficulty(difficulty);
+        Entrant {
+            name: name.to_string(),
+            reaction_ticks: ai.reaction_ticks,
+            max_speed: ai.max_speed,
+            aim_error: ai.aim_error,
+        }
+    }
+
+    pub fn controller(&self) -> components::AiController {
+        components::AiController {
+            reaction_ticks: self.reaction_ticks,
+            max_speed: self.max_speed,
+            aim_error: self.aim_error,
+            countdown: 0,
+            target: None,
+            seen: None,
+        }
+    }
+
+    pub fn load(path: &std::path::Path) -> std::io::Result<Vec<Entrant>> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        serde_json::from_reader(file).map_err(std::io::Error::from)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Format {
+    /// Every entrant plays every other one
+    RoundRobin,
+    /// Winners of a pairing advance to the next round until one is left
+    Elimination,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub format: Format,
+    /// Matches of every pairing, the entrants swap sides after every match
+    pub games: u32,
+    /// Matches played at the same time
+    pub threads: usize,
+    /// Seed of the first match, every following match increments it
+    pub seed: u64,
+    /// Ticks after which a match ends with its current score
+    pub max_ticks: u64,
+    pub points_to_win: u32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            format: Format::RoundRobin,
+            games: 2,
+            threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            seed: 0,
+            max_ticks: 2_000_000,
+            points_to_win: 11,
+        }
+    }
+}
+
+/// Outcome of a single match between the entrants on the `left` and `right`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub round: u32,
+    pub seed: u64,
+    pub left: usize,
+    pub right: usize,
+    pub score: [u32; 2],
+    pub ticks: u64,
+    pub longest_rally: u32,
+    /// Entrant leading once the match ended, `None` for a draw
+    pub winner: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    pub name: String,
+    pub played: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub points_for: u32,
+    pub points_against: u32,
+    /// Share of won matches, a draw counts half
+    pub win_rate: f64,
+    /// Bounds of the 95% Wilson score interval of the win rate
+    pub confidence: [f64; 2],
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub format: Format,
+    pub standings: Vec<Standing>,
+    pub matches: Vec<MatchResult>,
+    /// Winner of an elimination tournament
+    pub champion: Option<String>,
+}
+
+/// Match to be played, `index` orders the results independent of the threads
+#[derive(Clone, Copy, Debug)]
+struct Job {
+    index: usize,
+    round: u32,
+    left: usize,
+    right: usize,
+}
+
+/// Plays a tournament of `entrants` and ranks them by wins and point difference
+pub fn run(entrants: &[Entrant], config: &Config) -> Report {
+    let mut matches = Vec::new();
+    let mut champion = None;
+    match config.format {
+        Format::RoundRobin => {
+            let mut jobs = Vec::new();
+            for left in 0..entrants.len() {
+                for right in left + 1..entrants.len() {
+                    for game in 0..config.games {
+                        let (left, right) = if game % 2 == 0 {
+                            (left, right)
+                        } else {
+                            (right, left)
+                        };
+                        jobs.push(Job {
+                            index: jobs.len(),
+                            round: 0,
+                            left,
+                            right,
+                        });
+                    }
+                }
+            }
+            matches = play_all(entrants, config, &jobs);
+        }
+        Format::Elimination => {
+            let mut bracket: Vec<usize> = (0..entrants.len()).collect();
+            let mut round = 0;
+            while bracket.len() > 1 {
+                let mut jobs = Vec::new();
+                for pair in bracket.chunks(2).filter(|pair| pair.len() == 2) {
+                    for game in 0..config.games {
+                        let (left, right) = if game % 2 == 0 {
+                            (pair[0], pair[1])
+                        } else {
+                            (pair[1], pair[0])
+                        };
+                        jobs.push(Job {
+                            index: matches.len() + jobs.len(),
+                            round,
+                            left,
+                            right,
+                        });
+                    }
+                }
+                let results = play_all(entrants, config, &jobs);
+                // the last entrant of an odd bracket advances without playing
+                bracket = bracket
+                    .chunks(2)
+                    .map(|pair| match pair {
+                        [first, second] => advancing(&results, *first, *second),
+                        _ => pair[0],
+                    })
+                    .collect();
+                matches.extend(results);
+                round += 1;
+            }
+            champion = bracket.first().map(|winner| entrants[*winner].name.clone());
+        }
+    }
+    Report {
+        format: config.format,
+        standings: standings(entrants, &matches),
+        matches,
+        champion,
+    }
+}
+
+/// Winner of the matches between `first` and `second` by wins, then point
+/// difference and the better seed in the bracket
+pub(crate) fn advancing(results: &[MatchResult], first: usize, second: usize) -> usize {
+    let mut balance = (0, 0);
+    for result in results {
+        for (side, entrant) in [result.left, result.right].iter().enumerate() {
+            let sign = if *entrant == first { 1 } else { -1 };
+            if *entrant != first && *entrant != second {
+                continue;
+            }
+            if result.winner == Some(*entrant) {
+                balance.0 += sign;
+            }
+            balance.1 += sign * (result.score[side] as i64 - result.score[1 - side] as i64);
+        }
+    }
+    if balance >= (0, 0) {
+        first
+    } else {
+        second
+    }
+}
+
+/// Plays `jobs` on `config.threads` threads, every match in its own `Universe`
+fn play_all(entrants: &[Entrant], config: &Config, jobs: &[Job]) -> Vec<MatchResult> {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results = std::sync::Mutex::new(Vec::with_capacity(jobs.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..config.threads.max(1).min(jobs.len()) {
+            scope.spawn(|| loop {
+                let position = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let job = match jobs.get(position) {
+                    Some(job) => *job,
+                    None => break,
+                };
+                let result = play_match(entrants, config, job);
+                results.lock().unwrap().push((job.index, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn play_match(entrants: &[Entrant], config: &Config, job: Job) -> MatchResult {
+    let seed = config.seed.wrapping_add(job.index as u64);
+    let universe = Universe::new();
+    let mut world = universe.create_world();
+    let mut resources = Resources::default();
+    scene::insert_resources(&mut resources, seed);
+    resources
+        .get_mut::<components::MatchConfig>()
+        .unwrap()
+        .points_to_win = config.points_to_win;
+    scene::insert_components(&mut world);
+    let paddles: Vec<(Entity, usize)> = <Read<components::Player>>::query()
+        .filter(tag::<components::Barrier>())
+        .iter_entities(&world)
+        .map(|(entity, player)| (entity, player.index))
+        .collect();
+    for (paddle, index) in paddles {
+        let entrant = if index == 0 { job.left } else { job.right };
+        world
+            .remove_component::<components::InputController>(paddle)
+            .unwrap();
+        world
+            .add_component(paddle, entrants[entrant].controller())
+            .unwrap();
+    }
+    let mut schedule = scene::build_simulation().flush().build();
+
+    let mut ticks = 0;
+    let mut longest_rally = 0;
+    while ticks < config.max_ticks {
+        schedule.execute(&mut world, &mut resources);
+        ticks += 1;
+        longest_rally = longest_rally.max(resources.get::<components::Rally>().unwrap().hits);
+        if *resources.get::<components::GameState>().unwrap() == components::GameState::GameOver {
+            break;
+        }
+    }
+    let score = resources.get::<components::Match>().unwrap().score;
+    let score = [score[0], score[1]];
+    let winner = match score[0].cmp(&score[1]) {
+        std::cmp::Ordering::Greater => Some(job.left),
+        std::cmp::Ordering::Less => Some(job.right),
+        std::cmp::Ordering::Equal => None,
+    };
+    MatchResult {
+        round: job.round,
+        seed,
+        left: job.left,
+        right: job.right,
+        score,
+        ticks,
+        longest_rally,
+        winner,
+    }
+}
+
+pub(crate) fn standings(entrants: &[Entrant], matches: &[MatchResult]) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = entrants
+        .iter()
+        .map(|entrant| Standing {
+            name: entrant.name.clone(),
+            played: 0,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            points_for: 0,
+            points_against: 0,
+            win_rate: 0.0,
+            confidence: [0.0, 1.0],
+        })
+        .collect();
+    for result in matches {
+        for (side, entrant) in [result.left, result.right].iter().enumerate() {
+            let standing = &mut standings[*entrant];
+            standing.played += 1;
+            standing.points_for += result.score[side];
+            standing.points_against += result.score[1 - side];
+            match result.winner {
+                Some(winner) if winner == *entrant => standing.wins += 1,
+                Some(_) => standing.losses += 1,
+                None => standing.draws += 1,
+            }
+        }
+    }
+    for standing in &mut standings {
+        let successes = f64::from(standing.wins) + f64::from(standing.draws) / 2.0;
+        if standing.played > 0 {
+            standing.win_rate = successes / f64::from(standing.played);
+        }
+        standing.confidence = wilson_interval(successes, standing.played);
+    }
+    let mut order: Vec<usize> = (0..standings.len()).collect();
+    order.sort_by_key(|index| {
+        let standing = &standings[*index];
+        (
+            std::cmp::Reverse(2 * standing.wins + standing.draws),
+            std::cmp::Reverse(i64::from(standing.points_for) - i64::from(standing.points_against)),
+            *index,
+        )
+    });
+    order
+        .into_iter()
+        .map(|index| standings[index].clone())
+        .collect()
+}
+
+/// 95% Wilson score interval of `successes` in `trials`
+pub fn wilson_interval(successes: f64, trials: u32) -> [f64; 2] {
+    if trials == 0 {
+        return [0.0, 1.0];
+    }
+    const Z: f64 = 1.96;
+    let n = f64::from(trials);
+    let p = successes / n;
+    let denominator = 1.0 + Z * Z / n;
+    let center = (p + Z * Z / (2.0 * n)) / denominator;
+    let margin = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+    [(center - margin).max(0.0), (center + margin).min(1.0)]
+}
+
+impl Report {
+    pub fn save_json(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, self).map_err(std::io::Error::from)
+    }
+
+    pub fn write_standings_csv<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            "rank,name,played,wins,draws,losses,points_for,points_against,win_rate,ci_low,ci_high"
+        )?;
+        for (rank, standing) in self.standings.iter().enumerate() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{:.4},{:.4},{:.4}",
+                rank + 1,
+                csv_field(&standing.name),
+                standing.played,
+                standing.wins,
+                standing.draws,
+                standing.losses,
+                standing.points_for,
+                standing.points_against,
+                standing.win_rate,
+                standing.confidence[0],
+                standing.confidence[1]
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes one line per match, entrants are referred to by their index
+    pub fn write_matches_csv<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            "round,seed,left,right,left_score,right_score,ticks,longest_rally,winner"
+        )?;
+        for result in &self.matches {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                result.round,
+                result.seed,
+                result.left,
+                result.right,
+                result.score[0],
+                result.score[1],
+                result.ticks,
+                result.longest_rally,
+                result
+                    .winner
+                    .map_or_else(String::new, |winner| winner.to_string())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `value` if it would otherwise break the CSV structure
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}